@@ -0,0 +1,1513 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde_json::Value;
+
+use crate::config::{ConfigFiles, WORKSPACE_ENV_VAR};
+use crate::db::{to_stable_json, JiraDatabase};
+use crate::events::changes;
+use crate::export::{all_stories, export_csv, export_markdown};
+use crate::git::{apply_commit, check_message, install_hooks, read_head, read_history};
+use crate::import::{import_github_json, import_jira_csv, import_markdown, parse_status_mapping, ImportOptions, ImportReport};
+use crate::merge;
+use crate::rules::{apply_rules, DEFAULT_TIME_LIMIT_MS};
+use crate::server::{self, DEFAULT_PORT};
+use crate::models::{DBState, DbIndex, IdStrategy, ItemKind, Project, Sprint, Story};
+
+// runs a single command given on the command line instead of starting the interactive ui
+pub fn run(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(anyhow!("No command given"));
+    };
+
+    match command.as_str() {
+        "search" => search(db, args, out),
+        "query" => query(db, args, out),
+        "filter" => filter(db, args, out),
+        "sprint" => sprint(db, args, out),
+        "trash" => trash(db, args, out),
+        "archive" => archive(db, args, out),
+        "backup" => backup(db, args, out),
+        "log" => log(db, args, out),
+        "directory" => directory(db, args, out),
+        "merge" => merge_files(args, out),
+        "merge-file" => merge_directory_file(args, out),
+        "ids" => ids(db, args, out),
+        "project" => project(db, args, out),
+        "serve" => serve(db, args, out),
+        "webhooks" => webhooks(db, args, out),
+        "rules" => rules(db, args, out),
+        "git" => git(db, args, out),
+        "import" => import(db, args, out),
+        "export" => export(db, args, out),
+        "undo" if args.is_empty() => {
+            let operation = db.undo()?;
+            writeln!(out, "Undone: {operation}")?;
+            Ok(())
+        }
+        "redo" if args.is_empty() => {
+            let operation = db.redo()?;
+            writeln!(out, "Redone: {operation}")?;
+            Ok(())
+        }
+        command => Err(anyhow!("Unknown command: {command}")),
+    }
+}
+
+// reads the time given to --at, a date on its own means the end of that day
+pub fn parse_time(text: &str) -> Result<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    if let Ok(at) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+        return Ok(at.and_utc());
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(day.and_hms_opt(23, 59, 59).unwrap().and_utc());
+    }
+
+    Err(anyhow!("{text} is not a time, expected YYYY-MM-DD, \"YYYY-MM-DD HH:MM\" or an RFC 3339 timestamp"))
+}
+
+// the shell has already removed any quotes around phrases, so arguments containing spaces are quoted again
+fn join_query(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("\"{arg}\"")
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// removes the --archived flag from the arguments, returning whether it was there
+fn take_archived_flag(args: &[String]) -> (bool, Vec<String>) {
+    let include_archive = args.iter().any(|arg| arg == "--archived");
+    let args = args.iter().filter(|arg| *arg != "--archived").cloned().collect();
+    (include_archive, args)
+}
+
+// removes --project <KEY> from the arguments, returning the key if it was there
+fn take_project_flag(db: &JiraDatabase, args: Vec<String>) -> Result<(Option<String>, Vec<String>)> {
+    let Some(position) = args.iter().position(|arg| arg == "--project") else {
+        return Ok((None, args));
+    };
+
+    let key = args.get(position + 1).ok_or_else(|| anyhow!("--project needs a project key"))?.clone();
+    if !db.read_db()?.projects.contains_key(&key) {
+        return Err(anyhow!("There is no project with the key {key}"));
+    }
+
+    let args = args
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| *i != position && *i != position + 1)
+        .map(|(_, arg)| arg)
+        .collect();
+    Ok((Some(key), args))
+}
+
+fn query(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let (include_archive, args) = take_archived_flag(args);
+    let (project, args) = take_project_flag(db, args)?;
+
+    //a query is usually passed as one quoted argument, in which case it is used exactly as written
+    let query = match args.as_slice() {
+        [query] => query.clone(),
+        args => join_query(args),
+    };
+
+    let mut stories = if include_archive {
+        db.query_including_archive(&query)?
+    } else {
+        db.query(&query)?
+    };
+
+    if let Some(project) = project {
+        let db_state = db.read_db()?;
+        stories.retain(|(id, _)| db_state.project_of(*id) == Some(&project));
+    }
+
+    if stories.is_empty() {
+        writeln!(out, "No stories match {query}")?;
+    }
+
+    print_stories(db, &stories, out)
+}
+
+// one line per story, shown by its key
+fn print_stories(db: &JiraDatabase, stories: &[(DbIndex, Story)], out: &mut dyn Write) -> Result<()> {
+    let db_state = db.read_db()?;
+
+    for (id, story) in stories {
+        writeln!(out, "{} | {} | {}", db_state.display_key(*id), story.name, story.status)?;
+    }
+
+    Ok(())
+}
+
+fn filter(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: filter list | filter save <name> <query> | filter run <name> | filter delete <name> | filter pin <name> | filter unpin <name>";
+
+    let Some((subcommand, args)) = args.split_first() else {
+        return Err(anyhow!(usage));
+    };
+
+    match (subcommand.as_str(), args) {
+        ("list", []) => {
+            let db_state = db.read_db()?;
+
+            let mut names: Vec<&String> = db_state.filters.keys().collect();
+            names.sort();
+
+            for name in names {
+                let filter = &db_state.filters[name];
+                let pinned = if filter.pinned { " (pinned)" } else { "" };
+                writeln!(out, "{name}{pinned}: {}", filter.query)?;
+            }
+        }
+        ("save", [name, query @ ..]) if !query.is_empty() => {
+            let query = match query {
+                [query] => query.clone(),
+                query => join_query(query),
+            };
+            db.save_filter(name, &query)?;
+            writeln!(out, "Saved filter {name}")?;
+        }
+        ("run", [name]) => print_stories(db, &db.run_filter(name)?, out)?,
+        ("delete", [name]) => {
+            db.delete_filter(name)?;
+            writeln!(out, "Deleted filter {name}")?;
+        }
+        ("pin", [name]) => {
+            db.pin_filter(name, true)?;
+            writeln!(out, "Pinned filter {name}")?;
+        }
+        ("unpin", [name]) => {
+            db.pin_filter(name, false)?;
+            writeln!(out, "Unpinned filter {name}")?;
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn sprint(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: sprint list | sprint create <name> <start YYYY-MM-DD> <end YYYY-MM-DD> [goal] | sprint add <sprint> <story> \
+                 | sprint remove <sprint> <story> | sprint start <sprint> | sprint complete <sprint> | sprint show <sprint>";
+    let parse_date = |text: &String| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| anyhow!("{text} is not a date, expected YYYY-MM-DD"))
+    };
+
+    match args {
+        [subcommand] if subcommand == "list" => {
+            let db_state = db.read_db()?;
+
+            let mut sprint_ids: Vec<&DbIndex> = db_state.sprints.keys().collect();
+            sprint_ids.sort();
+
+            for id in sprint_ids {
+                let sprint = &db_state.sprints[id];
+                let stories = sprint.stories.len();
+                writeln!(out, "{} | {} | {} | {} to {} | {stories} stories", db_state.display_key(*id), sprint.name, sprint.state, sprint.start, sprint.end)?;
+            }
+        }
+        [subcommand, name, start, end, goal @ ..] if subcommand == "create" && goal.len() <= 1 => {
+            let goal = goal.first().cloned().unwrap_or_default();
+            let sprint_id = db.create_sprint(Sprint::new(name.clone(), goal, parse_date(start)?, parse_date(end)?))?;
+            writeln!(out, "Created sprint {}", db.read_db()?.display_key(sprint_id))?;
+        }
+        [subcommand, sprint, story] if subcommand == "add" => {
+            db.add_story_to_sprint(db.resolve_key(sprint)?, db.resolve_key(story)?)?;
+            writeln!(out, "Added {story} to sprint {sprint}")?;
+        }
+        [subcommand, sprint, story] if subcommand == "remove" => {
+            db.remove_story_from_sprint(db.resolve_key(sprint)?, db.resolve_key(story)?)?;
+            writeln!(out, "Removed {story} from sprint {sprint}")?;
+        }
+        [subcommand, sprint] if subcommand == "start" => {
+            db.start_sprint(db.resolve_key(sprint)?)?;
+            writeln!(out, "Started sprint {sprint}")?;
+        }
+        [subcommand, sprint] if subcommand == "complete" => match db.complete_sprint(db.resolve_key(sprint)?)? {
+            Some(next_id) => {
+                let next = db.read_db()?.display_key(next_id);
+                writeln!(out, "Completed sprint {sprint}, unfinished stories were moved to sprint {next}")?;
+            }
+            None => writeln!(out, "Completed sprint {sprint}, unfinished stories went back to the backlog")?,
+        },
+        [subcommand, sprint] if subcommand == "show" => {
+            let sprint_id = db.resolve_key(sprint)?;
+            let groups = db.get_sprint_stories_by_status(sprint_id)?;
+            let db_state = db.read_db()?;
+            let sprint = &db_state.sprints[&sprint_id];
+
+            writeln!(out, "{} ({}) {} to {}", sprint.name, sprint.state, sprint.start, sprint.end)?;
+            if !sprint.goal.is_empty() {
+                writeln!(out, "Goal: {}", sprint.goal)?;
+            }
+            for (status, stories) in groups {
+                writeln!(out, "{status}")?;
+                for (id, story) in stories {
+                    writeln!(out, "  {} | {}", db_state.display_key(id), story.name)?;
+                }
+            }
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn trash(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: trash list | trash restore <id> | trash purge <id> | trash retention <days|never>";
+
+    match args {
+        [subcommand] if subcommand == "list" => {
+            let db_state = db.read_db()?;
+
+            let mut items: Vec<(DbIndex, ItemKind, &String, DateTime<Utc>)> = db_state
+                .trash
+                .epics
+                .iter()
+                .map(|(id, trashed)| (*id, ItemKind::Epic, &trashed.epic.name, trashed.deleted_at))
+                .chain(
+                    db_state
+                        .trash
+                        .stories
+                        .iter()
+                        .map(|(id, trashed)| (*id, ItemKind::Story, &trashed.story.name, trashed.deleted_at)),
+                )
+                .collect();
+            items.sort_by_key(|(id, ..)| *id);
+
+            for (id, kind, name, deleted_at) in items {
+                let key = db_state.display_key(id);
+                writeln!(out, "{kind} {key} | {name} | deleted {}", deleted_at.format("%Y-%m-%d %H:%M"))?;
+            }
+        }
+        [subcommand, key] if subcommand == "restore" => {
+            let kind = db.restore(db.resolve_key(key)?)?;
+            writeln!(out, "Restored {kind} {key}")?;
+        }
+        [subcommand, key] if subcommand == "purge" => {
+            db.purge(db.resolve_key(key)?)?;
+            writeln!(out, "Purged {key}")?;
+        }
+        [subcommand, days] if subcommand == "retention" => {
+            let days = match days.as_str() {
+                "never" => None,
+                days => Some(days.parse().map_err(|_| anyhow!("{days} is not a number of days"))?),
+            };
+            db.update_trash_retention(days)?;
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn archive(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: archive | archive <epic id> | archive list";
+
+    match args {
+        [] => {
+            let epic_ids = db.archive_closed_epics()?;
+            let db_state = db.read_db()?;
+
+            if epic_ids.is_empty() {
+                writeln!(out, "No closed epics to archive")?;
+            }
+            for epic_id in epic_ids {
+                writeln!(out, "Archived EPIC {}", db_state.display_key(epic_id))?;
+            }
+        }
+        [subcommand] if subcommand == "list" => {
+            let archive = db.read_archive()?;
+            //the keys of archived items stay in the live database
+            let db_state = db.read_db()?;
+
+            let mut epic_ids: Vec<&DbIndex> = archive.epics.keys().collect();
+            epic_ids.sort();
+
+            for epic_id in epic_ids {
+                let epic = &archive.epics[epic_id];
+                let key = db_state.display_key(*epic_id);
+                writeln!(out, "EPIC {key} | {} | {} stories", epic.name, epic.stories.len())?;
+            }
+        }
+        [key] => {
+            db.archive_epic(db.resolve_key(key)?)?;
+            writeln!(out, "Archived EPIC {key}")?;
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn backup(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: backup list | backup restore <id>";
+
+    match args {
+        [subcommand] if subcommand == "list" => {
+            for backup in db.list_backups()? {
+                writeln!(
+                    out,
+                    "{} | {} | {} bytes",
+                    backup.id,
+                    backup.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    backup.size
+                )?;
+            }
+        }
+        [subcommand, id] if subcommand == "restore" => {
+            db.restore_backup(id)?;
+            writeln!(out, "Restored backup {id}")?;
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn log(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: log init <path> | log compact <YYYY-MM-DD>";
+
+    match args {
+        [subcommand, path] if subcommand == "init" => {
+            db.start_event_log(path.clone())?;
+            writeln!(out, "Started an event log at {path}")?;
+        }
+        [subcommand, date] if subcommand == "compact" => {
+            //everything from before the start of the given day is folded together
+            let before = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| anyhow!("{date} is not a date, expected YYYY-MM-DD"))?
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc();
+            let folded = db.compact_history(before)?;
+            writeln!(out, "Compacted {folded} events")?;
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn directory(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    match args {
+        [subcommand, path] if subcommand == "init" => {
+            db.start_directory(path.clone())?;
+            writeln!(out, "Wrote the database to {path}")?;
+            Ok(())
+        }
+        _ => Err(anyhow!("Usage: directory init <path>")),
+    }
+}
+
+// merges three db files the way git's merge drivers are called, writing the result over ours
+// the command fails when there are conflicts so that git leaves the file marked as conflicted
+fn merge_files(args: &[String], out: &mut dyn Write) -> Result<()> {
+    let [base_path, ours_path, theirs_path] = args else {
+        return Err(anyhow!("Usage: merge <base> <ours> <theirs>"));
+    };
+
+    let read = |path: &String| -> Result<DBState> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Couldn't read {path}"))?;
+        serde_json::from_str(&contents).with_context(|| format!("{path} is not a valid database"))
+    };
+    let outcome = merge::merge(&read(base_path)?, &read(ours_path)?, &read(theirs_path)?)?;
+
+    fs::write(ours_path, to_stable_json(&outcome.merged)?)?;
+
+    for (old_id, new_id) in &outcome.renumbered {
+        writeln!(out, "Their item {old_id} is now {new_id}")?;
+    }
+    for conflict in &outcome.conflicts {
+        writeln!(out, "CONFLICT {conflict}")?;
+    }
+
+    if !outcome.conflicts.is_empty() {
+        return Err(anyhow!(
+            "{} conflicts, our side was kept for each of them",
+            outcome.conflicts.len()
+        ));
+    }
+
+    Ok(())
+}
+
+// the merge driver for one file of a directory database, git leaves the base empty when both sides added the file
+fn merge_directory_file(args: &[String], out: &mut dyn Write) -> Result<()> {
+    let [base_path, ours_path, theirs_path, path] = args else {
+        return Err(anyhow!("Usage: merge-file <base> <ours> <theirs> <path in the repo>"));
+    };
+
+    let read = |file: &String| -> Result<Option<Value>> {
+        let contents = fs::read_to_string(file).with_context(|| format!("Couldn't read {file}"))?;
+        if contents.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&contents).with_context(|| format!("{path} is not valid json"))?))
+    };
+    let (Some(ours), Some(theirs)) = (read(ours_path)?, read(theirs_path)?) else {
+        return Err(anyhow!("{path} is empty on one side, it has to be merged by hand"));
+    };
+    let (merged, conflicts) = merge::merge_file(path, read(base_path)?.as_ref(), &ours, &theirs);
+
+    fs::write(ours_path, to_stable_json(&merged)?)?;
+
+    for conflict in &conflicts {
+        writeln!(out, "CONFLICT {conflict}")?;
+    }
+    if !conflicts.is_empty() {
+        return Err(anyhow!("{} conflicts, our side was kept for each of them", conflicts.len()));
+    }
+
+    Ok(())
+}
+
+// the workspace command is run before a database is opened, so it still works when the active workspace is broken
+pub fn workspace(files: &mut ConfigFiles, args: &[String], out: &mut dyn Write) -> Result<()> {
+    match args {
+        [] => list_workspaces(files, out)?,
+        [subcommand] if subcommand == "list" => list_workspaces(files, out)?,
+        [subcommand, name] if subcommand == "switch" => {
+            files.switch_workspace(name)?;
+            writeln!(out, "Switched to {name}")?;
+
+            if let Some(env_workspace) = files.env_workspace.as_ref().filter(|env_workspace| *env_workspace != name) {
+                writeln!(out, "{WORKSPACE_ENV_VAR} is set to {env_workspace}, which is used instead while it is set")?;
+            }
+        }
+        _ => return Err(anyhow!("Usage: workspace [list] | workspace switch <name>")),
+    }
+
+    Ok(())
+}
+
+fn webhooks(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    match args {
+        [] => {
+            let pending = db.pending_deliveries()?;
+            if pending.is_empty() {
+                writeln!(out, "No webhook events are waiting to be sent")?;
+            }
+
+            for delivery in pending {
+                let error = delivery.last_error.as_deref().unwrap_or("not tried yet");
+                writeln!(
+                    out,
+                    "{} to {} | {} attempts | next {} | {error}",
+                    delivery.event["event"].as_str().unwrap_or_default(),
+                    delivery.url,
+                    delivery.attempts,
+                    delivery.next_attempt.format("%Y-%m-%d %H:%M:%S")
+                )?;
+            }
+        }
+        [subcommand] if subcommand == "retry" => {
+            let delivered = db.retry_deliveries()?;
+            let remaining = db.pending_deliveries()?.len();
+            writeln!(out, "Sent {delivered} webhook events, {remaining} still waiting")?;
+        }
+        _ => return Err(anyhow!("Usage: webhooks [retry]")),
+    }
+
+    Ok(())
+}
+
+fn rules(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    match args {
+        [] => {
+            let rules = db.rules();
+            if rules.is_empty() {
+                writeln!(out, "No rules are configured")?;
+            }
+
+            for rule in rules {
+                let time_limit = rule.time_limit_ms.unwrap_or(DEFAULT_TIME_LIMIT_MS);
+                writeln!(out, "{} | {} | {time_limit}ms", rule.event, rule.script.display())?;
+            }
+        }
+        [subcommand, command @ ..] if subcommand == "dry-run" && !command.is_empty() => {
+            if !only_changes_the_database(command) {
+                return Err(anyhow!("{} can't be dry run", command.join(" ")));
+            }
+
+            //the command is run on a copy without the rules, and then the rules are run over what it changed
+            let before = db.read_db()?;
+            let copy = JiraDatabase::in_memory(before.clone(), db.read_archive()?);
+            run(&copy, command, out)?;
+
+            let after = copy.read_db()?;
+            let with_rules = apply_rules(&db.rules(), &before, &after)?;
+            let events = changes(&after, &with_rules, Utc::now());
+            if events.is_empty() {
+                writeln!(out, "No rules would change anything")?;
+            }
+
+            for event in events {
+                writeln!(out, "Rules would make {}", describe_event(&event))?;
+            }
+            writeln!(out, "Nothing was saved")?;
+        }
+        _ => return Err(anyhow!("Usage: rules [dry-run <command> [args]]")),
+    }
+
+    Ok(())
+}
+
+// whether a command changes nothing but the database, so that running it on a copy really does save nothing
+// anything else, like export --output or git install-hooks, writes files of its own that a dry run can't swap out
+fn only_changes_the_database(command: &[String]) -> bool {
+    let args: Vec<&str> = command.iter().map(String::as_str).collect();
+
+    match args[..] {
+        ["search" | "query" | "filter" | "sprint" | "trash" | "archive" | "ids" | "project" | "undo" | "redo", ..] => true,
+        ["git", "scan" | "post-commit", ..] => true,
+        //the id mapping is written to a file after the import
+        ["import", ..] => !args.contains(&"--ids"),
+        _ => false,
+    }
+}
+
+// links commits to the stories their messages mention, --resolve also resolves the stories a commit fixes
+fn git(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let resolve = args.iter().any(|arg| arg == "--resolve");
+    let args: Vec<&str> = args.iter().filter(|arg| *arg != "--resolve").map(|arg| arg.as_str()).collect();
+
+    match args[..] {
+        ["scan", ref repo @ ..] if repo.len() <= 1 => {
+            let history = read_history(Path::new(repo.first().unwrap_or(&".")))?;
+            for commit in &history {
+                for line in apply_commit(db, commit, resolve)? {
+                    writeln!(out, "{line}")?;
+                }
+            }
+            writeln!(out, "Scanned {} commits", history.len())?;
+        }
+        ["check-message", path] => {
+            let message = fs::read_to_string(path).with_context(|| format!("Couldn't read the commit message in {path}"))?;
+            check_message(db, &message)?;
+        }
+        ["post-commit"] => {
+            let commit = read_head(Path::new("."))?;
+            for line in apply_commit(db, &commit, resolve)? {
+                writeln!(out, "{line}")?;
+            }
+        }
+        ["install-hooks", ref repo @ ..] if repo.len() <= 1 => {
+            //the hooks run whichever program installed them, so they keep working when it isn't on the PATH
+            let program = std::env::current_exe().context("Couldn't find this program to run from the hooks")?;
+            for path in install_hooks(Path::new(repo.first().unwrap_or(&".")), &program, resolve)? {
+                writeln!(out, "Installed {}", path.display())?;
+            }
+        }
+        _ => {
+            return Err(anyhow!(
+                "Usage: git scan [repo] [--resolve] | git install-hooks [repo] [--resolve] | git check-message <file> | git post-commit [--resolve]"
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+const EXPORT_USAGE: &str = "Usage: export <csv|markdown> [query | --filter <name>] [--output <file>]";
+
+// writes the stories a query or saved filter finds, or every story, for people who don't use this program
+fn export(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let Some((format, args)) = args.split_first() else {
+        return Err(anyhow!(EXPORT_USAGE));
+    };
+
+    let mut query = vec![];
+    let mut filter = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => filter = Some(args.next().ok_or_else(|| anyhow!("--filter needs the name of a saved filter"))?),
+            "--output" => output = Some(args.next().ok_or_else(|| anyhow!("--output needs a file"))?),
+            _ => query.push(arg.clone()),
+        }
+    }
+
+    let db_state = db.read_db()?;
+    let (stories, title) = match (filter, query.as_slice()) {
+        (None, []) => (all_stories(&db_state), "Backlog".to_owned()),
+        (None, [query]) => (db.query(query)?, format!("Stories matching {query}")),
+        (None, query) => {
+            let query = join_query(query);
+            (db.query(&query)?, format!("Stories matching {query}"))
+        }
+        (Some(name), []) => (db.run_filter(name)?, format!("Filter {name}")),
+        (Some(_), _) => return Err(anyhow!("Export either a query or a saved filter, not both")),
+    };
+
+    let contents = match format.as_str() {
+        "csv" => export_csv(&db_state, &stories)?,
+        "markdown" | "md" => export_markdown(&db_state, &stories, &title),
+        _ => return Err(anyhow!(EXPORT_USAGE)),
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, contents).with_context(|| format!("Couldn't write {path}"))?;
+            writeln!(out, "Exported {} stories to {path}", stories.len())?;
+        }
+        None => write!(out, "{contents}")?,
+    }
+
+    Ok(())
+}
+
+const IMPORT_USAGE: &str = "Usage: import <jira|github|markdown> <file> [--dry-run] [--status <jira status>=<status>]... [--ids <mapping.json>] [--project <KEY>]";
+
+// imports the issues of another tracker, --ids keeps a mapping from their keys to the keys here so importing again only adds new ones
+// github issue dumps and markdown task lists are matched by name instead, so they can be imported again without a mapping
+fn import(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let [format, path, flags @ ..] = args else {
+        return Err(anyhow!(IMPORT_USAGE));
+    };
+
+    let mut options = ImportOptions::default();
+    let mut ids_path = None;
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let mut value = || flags.next().ok_or_else(|| anyhow!("{flag} needs a value"));
+        match flag.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--status" => options.status_map.extend([parse_status_mapping(value()?)?]),
+            "--ids" => ids_path = Some(value()?.clone()),
+            "--project" => {
+                let key = value()?;
+                if !db.read_db()?.projects.contains_key(key) {
+                    return Err(anyhow!("There is no project with the key {key}"));
+                }
+                options.project = Some(key.clone());
+            }
+            _ => return Err(anyhow!(IMPORT_USAGE)),
+        }
+    }
+
+    //the mapping file only exists after the first import
+    if let Some(contents) = ids_path.as_ref().and_then(|ids_path| fs::read_to_string(ids_path).ok()) {
+        options.known_ids = serde_json::from_str(&contents).with_context(|| format!("{} isn't an id mapping", ids_path.as_ref().unwrap()))?;
+    }
+
+    let contents = fs::read_to_string(path).with_context(|| format!("Couldn't read {path}"))?;
+    let report = match format.as_str() {
+        "jira" => import_jira_csv(db, &contents, &options)?,
+        "github" => import_github_json(db, &contents, &options)?,
+        "markdown" | "md" => import_markdown(db, &contents, &options)?,
+        _ => return Err(anyhow!(IMPORT_USAGE)),
+    };
+
+    print_import_report(&report, &options, out)?;
+    if let (Some(ids_path), false, true) = (ids_path, options.dry_run, report.problems.is_empty()) {
+        fs::write(&ids_path, to_stable_json(&report.ids)?)?;
+        writeln!(out, "Saved the id mapping in {ids_path}")?;
+    }
+
+    Ok(())
+}
+
+fn print_import_report(report: &ImportReport, options: &ImportOptions, out: &mut dyn Write) -> Result<()> {
+    for warning in &report.warnings {
+        writeln!(out, "Warning: {warning}")?;
+    }
+    for problem in &report.problems {
+        writeln!(out, "Problem: {problem}")?;
+    }
+
+    if options.dry_run {
+        //the dry run shows where everything would end up, including what was imported before
+        for (their_key, our_key) in &report.ids {
+            writeln!(out, "{their_key} -> {our_key}")?;
+        }
+        writeln!(out, "Would import {} epics and {} stories{}, nothing was saved", report.epics, report.stories, updated(report, "update"))?;
+    } else if !report.problems.is_empty() {
+        writeln!(out, "Nothing was imported because of the problems above")?;
+    } else {
+        writeln!(out, "Imported {} epics and {} stories{}", report.epics, report.stories, updated(report, "updated"))?;
+    }
+
+    Ok(())
+}
+
+// only shown when something imported before had its status changed
+fn updated(report: &ImportReport, verb: &str) -> String {
+    match report.updated {
+        0 => "".to_owned(),
+        updated => format!(" and {verb} the status of {updated} items imported before"),
+    }
+}
+
+// an event on one line, like story.status_changed WEB-3: "Open" -> "Resolved"
+fn describe_event(event: &Value) -> String {
+    let event_type = event["event"].as_str().unwrap_or_default();
+    let key = event["item"]["key"].as_str().unwrap_or_default();
+
+    match (&event["old"], &event["new"]) {
+        (Value::Null, new) if event_type.ends_with(".created") => format!("{event_type} {key} {}", new["name"]),
+        (old, new) if event_type.ends_with("_changed") => format!("{event_type} {key}: {old} -> {new}"),
+        _ => format!("{event_type} {key}"),
+    }
+}
+
+// serves the json api on localhost until the process is stopped
+fn serve(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let port = match args {
+        [] => DEFAULT_PORT,
+        [port] => port.parse().with_context(|| format!("{port} is not a port number"))?,
+        _ => return Err(anyhow!("Usage: serve [port]")),
+    };
+
+    //only bound to localhost, there is no authentication
+    let http_server = tiny_http::Server::http(("127.0.0.1", port)).map_err(|error| anyhow!("Couldn't listen on port {port}: {error}"))?;
+    writeln!(out, "Listening on http://127.0.0.1:{port}")?;
+    out.flush()?;
+
+    server::serve(&http_server, db);
+    Ok(())
+}
+
+// the active workspace is marked with a *
+fn list_workspaces(files: &ConfigFiles, out: &mut dyn Write) -> Result<()> {
+    if files.config.workspaces.is_empty() {
+        writeln!(out, "No workspaces are configured, the database in ./data is used")?;
+    }
+
+    for (name, workspace) in &files.config.workspaces {
+        let marker = if files.active_name() == Some(name) { "*" } else { " " };
+        writeln!(out, "{marker} {name} | {} | {}", workspace.backend, workspace.path.display())?;
+    }
+
+    Ok(())
+}
+
+fn project(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: project list | project create <KEY> <name> [description] | project default <KEY> | project show <KEY>";
+
+    match args {
+        [subcommand] if subcommand == "list" => {
+            let db_state = db.read_db()?;
+
+            for (key, project) in &db_state.projects {
+                let epics = project.keys.keys().filter(|id| db_state.epics.contains_key(*id)).count();
+                let default = if db_state.default_project.as_ref() == Some(key) { " (default)" } else { "" };
+                writeln!(out, "{key}{default} | {} | {epics} epics", project.name)?;
+            }
+        }
+        [subcommand, key, name, description @ ..] if subcommand == "create" && description.len() <= 1 => {
+            let description = description.first().cloned().unwrap_or_default();
+            db.create_project(key, Project::new(name.clone(), description))?;
+            writeln!(out, "Created project {key}")?;
+        }
+        [subcommand, key] if subcommand == "default" => db.set_default_project(key)?,
+        [subcommand, key] if subcommand == "show" => {
+            let epic_ids = db.get_project_epics(key)?;
+            let db_state = db.read_db()?;
+
+            for epic_id in epic_ids {
+                let epic = &db_state.epics[&epic_id];
+                writeln!(out, "{} | {} | {}", db_state.display_key(epic_id), epic.name, epic.status)?;
+            }
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn ids(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let usage = "Usage: ids | ids strategy <sequential|time|site <number>> | ids prefix <PREFIX|none>";
+
+    match args {
+        [] => {
+            let db_state = db.read_db()?;
+            let prefix = db_state.key_prefix.as_deref().unwrap_or("none");
+            writeln!(out, "strategy: {}, key prefix: {prefix}", db_state.id_strategy)?;
+        }
+        [subcommand, strategy @ ..] if subcommand == "strategy" => {
+            let strategy = match strategy {
+                [name] if name == "sequential" => IdStrategy::Sequential,
+                [name] if name == "time" => IdStrategy::Time,
+                [name, site] if name == "site" => IdStrategy::Site {
+                    site: site.parse().map_err(|_| anyhow!("{site} is not a site number"))?,
+                },
+                _ => return Err(anyhow!(usage)),
+            };
+            db.update_id_strategy(strategy)?;
+        }
+        [subcommand, prefix] if subcommand == "prefix" => {
+            let prefix = match prefix.as_str() {
+                "none" => None,
+                prefix => Some(prefix.to_owned()),
+            };
+            db.update_key_prefix(prefix)?;
+        }
+        _ => return Err(anyhow!(usage)),
+    }
+
+    Ok(())
+}
+
+fn search(db: &JiraDatabase, args: &[String], out: &mut dyn Write) -> Result<()> {
+    let (include_archive, args) = take_archived_flag(args);
+    let (project, args) = take_project_flag(db, args)?;
+    let query = join_query(&args);
+
+    if query.trim().is_empty() {
+        return Err(anyhow!("Usage: search [--archived] [--project <KEY>] <terms or \"phrases\">"));
+    }
+
+    let mut results = if include_archive {
+        db.search_including_archive(&query)?
+    } else {
+        db.search(&query)?
+    };
+
+    let db_state = db.read_db()?;
+    if let Some(project) = project {
+        results.retain(|result| db_state.project_of(result.id) == Some(&project));
+    }
+
+    if results.is_empty() {
+        writeln!(out, "No results for {query}")?;
+    }
+
+    for result in results {
+        writeln!(out, "{} {}: {}", result.kind, db_state.display_key(result.id), result.snippet)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use crate::models::{BackupConfig, Epic, Status};
+
+    fn run_to_string(db: &JiraDatabase, args: &[&str]) -> Result<String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut out = vec![];
+        run(db, &args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn run_should_error_for_unknown_command() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert!(run_to_string(&db, &[]).is_err());
+        assert!(run_to_string(&db, &["j983f2j"]).is_err());
+    }
+
+    #[test]
+    fn parse_time_should_accept_dates_and_times() {
+        assert_eq!(parse_time("2026-10-16").unwrap().to_rfc3339(), "2026-10-16T23:59:59+00:00");
+        assert_eq!(parse_time("2026-10-16 09:30").unwrap().to_rfc3339(), "2026-10-16T09:30:00+00:00");
+        assert_eq!(
+            parse_time("2026-10-16T09:30:00+02:00").unwrap().to_rfc3339(),
+            "2026-10-16T07:30:00+00:00"
+        );
+        assert!(parse_time("friday").is_err());
+    }
+
+    #[test]
+    fn join_query_should_quote_phrases() {
+        let args = vec!["login".to_owned(), "remember me".to_owned()];
+
+        assert_eq!(join_query(&args), r#"login "remember me""#);
+    }
+
+    #[test]
+    fn search_should_error_without_terms() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert!(run_to_string(&db, &["search"]).is_err());
+    }
+
+    #[test]
+    fn search_should_print_results() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db
+            .create_epic(Epic::new("Accounts".to_owned(), "Login and sign up".to_owned()))
+            .unwrap();
+        db.create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+
+        let output = run_to_string(&db, &["search", "login"]).unwrap();
+        assert_eq!(output, "STORY 2: **Login** page\nEPIC 1: **Login** and sign up\n");
+
+        let output = run_to_string(&db, &["search", "missing"]).unwrap();
+        assert_eq!(output, "No results for missing\n");
+    }
+
+    #[test]
+    fn query_should_print_matching_stories() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+        db.create_story(Story::new("Sign up".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+
+        let output = run_to_string(&db, &["query", r#"name ~ "login page""#]).unwrap();
+        assert_eq!(output, "2 | Login page | OPEN\n");
+
+        let output = run_to_string(&db, &["query", "name", "~", "login page"]).unwrap();
+        assert_eq!(output, "2 | Login page | OPEN\n");
+
+        let output = run_to_string(&db, &["query", "order", "by", "id", "desc"]).unwrap();
+        assert_eq!(output, "3 | Sign up | OPEN\n2 | Login page | OPEN\n");
+
+        let error = run_to_string(&db, &["query", "nmae ~ login"]).unwrap_err();
+        assert_eq!(error.to_string(), "column 1: unknown field 'nmae'\nnmae ~ login\n^");
+    }
+
+    #[test]
+    fn filter_should_error_for_bad_usage() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert!(run_to_string(&db, &["filter"]).is_err());
+        assert!(run_to_string(&db, &["filter", "save", "name"]).is_err());
+        assert!(run_to_string(&db, &["filter", "run"]).is_err());
+    }
+
+    #[test]
+    fn filter_should_save_list_and_run_filters() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+
+        let output = run_to_string(&db, &["filter", "save", "logins", r#"name ~ "login""#]).unwrap();
+        assert_eq!(output, "Saved filter logins\n");
+
+        let output = run_to_string(&db, &["filter", "pin", "logins"]).unwrap();
+        assert_eq!(output, "Pinned filter logins\n");
+
+        let output = run_to_string(&db, &["filter", "list"]).unwrap();
+        assert_eq!(output, "logins (pinned): name ~ \"login\"\n");
+
+        let output = run_to_string(&db, &["filter", "run", "logins"]).unwrap();
+        assert_eq!(output, "2 | Login page | OPEN\n");
+
+        let output = run_to_string(&db, &["filter", "unpin", "logins"]).unwrap();
+        assert_eq!(output, "Unpinned filter logins\n");
+
+        let output = run_to_string(&db, &["filter", "delete", "logins"]).unwrap();
+        assert_eq!(output, "Deleted filter logins\n");
+
+        assert!(run_to_string(&db, &["filter", "run", "logins"]).is_err());
+    }
+
+    #[test]
+    fn sprint_should_plan_run_and_show_a_sprint() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        let login = db.create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id).unwrap();
+        let logout = db.create_story(Story::new("Logout".to_owned(), "".to_owned()), epic_id).unwrap();
+        db.update_story_status(logout, Status::Closed).unwrap();
+
+        let output = run_to_string(&db, &["sprint", "create", "Sprint 1", "2024-01-01", "2024-01-14", "Ship logins"]).unwrap();
+        assert_eq!(output, "Created sprint 4\n");
+        run_to_string(&db, &["sprint", "create", "Sprint 2", "2024-01-15", "2024-01-28"]).unwrap();
+
+        assert_eq!(run_to_string(&db, &["sprint", "add", "4", &login.to_string()]).unwrap(), "Added 2 to sprint 4\n");
+        run_to_string(&db, &["sprint", "add", "4", &logout.to_string()]).unwrap();
+        assert_eq!(run_to_string(&db, &["sprint", "start", "4"]).unwrap(), "Started sprint 4\n");
+
+        let output = run_to_string(&db, &["sprint", "show", "4"]).unwrap();
+        assert_eq!(
+            output,
+            "Sprint 1 (ACTIVE) 2024-01-01 to 2024-01-14\nGoal: Ship logins\nOPEN\n  2 | Login page\nIN PROGRESS\nRESOLVED\nCLOSED\n  3 | Logout\n"
+        );
+
+        let output = run_to_string(&db, &["sprint", "complete", "4"]).unwrap();
+        assert_eq!(output, "Completed sprint 4, unfinished stories were moved to sprint 5\n");
+
+        let output = run_to_string(&db, &["sprint", "list"]).unwrap();
+        assert_eq!(
+            output,
+            "4 | Sprint 1 | COMPLETED | 2024-01-01 to 2024-01-14 | 1 stories\n5 | Sprint 2 | PLANNED | 2024-01-15 to 2024-01-28 | 1 stories\n"
+        );
+
+        assert_eq!(run_to_string(&db, &["sprint", "remove", "5", "2"]).unwrap(), "Removed 2 from sprint 5\n");
+        assert!(run_to_string(&db, &["sprint", "create", "Sprint 3", "someday", "2024-02-01"]).is_err());
+        assert!(run_to_string(&db, &["sprint", "start"]).is_err());
+    }
+
+    #[test]
+    fn trash_should_list_restore_and_purge() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        let story_id = db
+            .create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+        db.delete_story(epic_id, story_id).unwrap();
+
+        let output = run_to_string(&db, &["trash", "list"]).unwrap();
+        assert!(output.starts_with("STORY 2 | Login page | deleted "));
+
+        let output = run_to_string(&db, &["trash", "restore", "2"]).unwrap();
+        assert_eq!(output, "Restored STORY 2\n");
+
+        db.delete_epic(epic_id).unwrap();
+
+        let output = run_to_string(&db, &["trash", "purge", "1"]).unwrap();
+        assert_eq!(output, "Purged 1\n");
+
+        let output = run_to_string(&db, &["trash", "list"]).unwrap();
+        assert_eq!(output, "");
+
+        assert!(run_to_string(&db, &["trash", "restore", "one"]).is_err());
+        assert!(run_to_string(&db, &["trash", "retention", "never"]).is_ok());
+        assert!(run_to_string(&db, &["trash"]).is_err());
+    }
+
+    #[test]
+    fn archive_should_move_closed_epics_out_of_search_and_query() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+
+        let output = run_to_string(&db, &["archive"]).unwrap();
+        assert_eq!(output, "No closed epics to archive\n");
+
+        db.update_epic_status(epic_id, Status::Closed).unwrap();
+
+        let output = run_to_string(&db, &["archive"]).unwrap();
+        assert_eq!(output, "Archived EPIC 1\n");
+
+        let output = run_to_string(&db, &["archive", "list"]).unwrap();
+        assert_eq!(output, "EPIC 1 | Accounts | 1 stories\n");
+
+        let output = run_to_string(&db, &["search", "login"]).unwrap();
+        assert_eq!(output, "No results for login\n");
+
+        let output = run_to_string(&db, &["search", "--archived", "login"]).unwrap();
+        assert_eq!(output, "STORY 2: **Login** page\n");
+
+        let output = run_to_string(&db, &["query", "--archived", "status = open"]).unwrap();
+        assert_eq!(output, "2 | Login page | OPEN\n");
+
+        assert!(run_to_string(&db, &["archive", "one"]).is_err());
+        assert!(run_to_string(&db, &["archive", "1", "2"]).is_err());
+    }
+
+    #[test]
+    fn backup_should_list_and_restore_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("db.json").to_str().unwrap().to_owned();
+        std::fs::write(&file_path, r#"{ "last_item_id": 0, "epics": {}, "stories": {} }"#).unwrap();
+
+        let db = JiraDatabase::with_backups(file_path, BackupConfig::default());
+        db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+        let output = run_to_string(&db, &["backup", "list"]).unwrap();
+        let id = output.split(" | ").next().unwrap().to_owned();
+        assert!(output.ends_with(" bytes\n"));
+
+        let output = run_to_string(&db, &["backup", "restore", &id]).unwrap();
+        assert_eq!(output, format!("Restored backup {id}\n"));
+        assert!(db.read_db().unwrap().epics.is_empty());
+
+        assert!(run_to_string(&db, &["backup", "restore", "missing"]).is_err());
+        assert!(run_to_string(&db, &["backup"]).is_err());
+    }
+
+    #[test]
+    fn log_should_start_and_compact_an_event_log() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("db.events.jsonl").to_str().unwrap().to_owned();
+
+        let output = run_to_string(&db, &["log", "init", &log_path]).unwrap();
+        assert_eq!(output, format!("Started an event log at {log_path}\n"));
+        assert!(run_to_string(&db, &["log", "init", &log_path]).is_err());
+
+        let event_log = JiraDatabase::with_event_log(log_path);
+        assert_eq!(event_log.read_db().unwrap().epics[&1].name, "Accounts");
+
+        event_log.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+        let output = run_to_string(&event_log, &["log", "compact", "2999-01-01"]).unwrap();
+        assert!(output.starts_with("Compacted "));
+        assert_eq!(event_log.read_db().unwrap().epics.len(), 2);
+
+        assert!(run_to_string(&event_log, &["log", "compact", "yesterday"]).is_err());
+        assert!(run_to_string(&db, &["log", "compact", "2999-01-01"]).is_err());
+    }
+
+    #[test]
+    fn directory_init_should_copy_the_database() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db").to_str().unwrap().to_owned();
+
+        let output = run_to_string(&db, &["directory", "init", &path]).unwrap();
+        assert_eq!(output, format!("Wrote the database to {path}\n"));
+
+        let directory = JiraDatabase::with_directory(path);
+        assert_eq!(directory.read_db().unwrap(), db.read_db().unwrap());
+
+        assert!(run_to_string(&db, &["directory", "init"]).is_err());
+    }
+
+    #[test]
+    fn merge_should_write_the_result_over_ours() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+
+        let mut base = DBState::default();
+        base.epics.insert(1, Epic::new("Accounts".to_owned(), "".to_owned()));
+        base.last_item_id = 1;
+        let mut ours = base.clone();
+        ours.epics.get_mut(&1).unwrap().status = Status::Closed;
+        let mut theirs = base.clone();
+        theirs.epics.get_mut(&1).unwrap().name = "User accounts".to_owned();
+
+        for (name, state) in [("base", &base), ("ours", &ours), ("theirs", &theirs)] {
+            std::fs::write(path(name), serde_json::to_string(state).unwrap()).unwrap();
+        }
+
+        let output = run_to_string(&db, &["merge", &path("base"), &path("ours"), &path("theirs")]).unwrap();
+        assert_eq!(output, "");
+
+        let merged: DBState = serde_json::from_str(&std::fs::read_to_string(path("ours")).unwrap()).unwrap();
+        assert_eq!(merged.epics[&1].name, "User accounts");
+        assert_eq!(merged.epics[&1].status, Status::Closed);
+
+        //changing the same field on both sides is a conflict
+        std::fs::write(path("base"), serde_json::to_string(&merged).unwrap()).unwrap();
+        theirs = merged.clone();
+        theirs.epics.get_mut(&1).unwrap().status = Status::Resolved;
+        std::fs::write(path("theirs"), serde_json::to_string(&theirs).unwrap()).unwrap();
+        ours = merged;
+        ours.epics.get_mut(&1).unwrap().status = Status::Open;
+        std::fs::write(path("ours"), serde_json::to_string(&ours).unwrap()).unwrap();
+
+        let error = run_to_string(&db, &["merge", &path("base"), &path("ours"), &path("theirs")]).unwrap_err();
+        assert_eq!(error.to_string(), "1 conflicts, our side was kept for each of them");
+
+        assert!(run_to_string(&db, &["merge", &path("base")]).is_err());
+    }
+
+    #[test]
+    fn ids_should_change_strategy_and_show_keys() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        let output = run_to_string(&db, &["ids"]).unwrap();
+        assert_eq!(output, "strategy: sequential, key prefix: none\n");
+
+        run_to_string(&db, &["ids", "strategy", "site", "2"]).unwrap();
+        run_to_string(&db, &["ids", "prefix", "WEB"]).unwrap();
+
+        let output = run_to_string(&db, &["ids"]).unwrap();
+        assert_eq!(output, "strategy: site 2, key prefix: WEB\n");
+
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login page".to_owned(), "".to_owned()), epic_id)
+            .unwrap();
+
+        let output = run_to_string(&db, &["query", "name ~ login"]).unwrap();
+        assert_eq!(output, "WEB-2 | Login page | OPEN\n");
+
+        let output = run_to_string(&db, &["search", "login"]).unwrap();
+        assert_eq!(output, "STORY WEB-2: **Login** page\n");
+
+        db.delete_epic(epic_id).unwrap();
+        let output = run_to_string(&db, &["trash", "restore", "WEB-1"]).unwrap();
+        assert_eq!(output, "Restored EPIC WEB-1\n");
+
+        assert!(run_to_string(&db, &["ids", "strategy", "site", "two"]).is_err());
+        assert!(run_to_string(&db, &["ids", "prefix", "web"]).is_err());
+    }
+
+    #[test]
+    fn project_should_create_list_and_scope_commands() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        let output = run_to_string(&db, &["project", "create", "WEB", "Website"]).unwrap();
+        assert_eq!(output, "Created project WEB\n");
+        run_to_string(&db, &["project", "create", "APP", "App", "The mobile app"]).unwrap();
+
+        let web_epic = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        let app_epic = db.create_epic_in_project("APP", Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login page".to_owned(), "".to_owned()), web_epic).unwrap();
+        db.create_story(Story::new("Login screen".to_owned(), "".to_owned()), app_epic).unwrap();
+
+        let output = run_to_string(&db, &["project", "list"]).unwrap();
+        assert_eq!(output, "APP | App | 1 epics\nWEB (default) | Website | 1 epics\n");
+
+        let output = run_to_string(&db, &["project", "show", "APP"]).unwrap();
+        assert_eq!(output, "APP-1 | Accounts | OPEN\n");
+
+        let output = run_to_string(&db, &["search", "--project", "APP", "login"]).unwrap();
+        assert_eq!(output, "STORY APP-2: **Login** screen\n");
+
+        let output = run_to_string(&db, &["query", "name ~ login", "--project", "WEB"]).unwrap();
+        assert_eq!(output, "WEB-2 | Login page | OPEN\n");
+
+        run_to_string(&db, &["project", "default", "APP"]).unwrap();
+        assert_eq!(db.read_db().unwrap().default_project, Some("APP".to_owned()));
+
+        assert!(run_to_string(&db, &["search", "--project", "NOPE", "login"]).is_err());
+        assert!(run_to_string(&db, &["search", "login", "--project"]).is_err());
+        assert!(run_to_string(&db, &["project", "create", "WEB"]).is_err());
+    }
+
+    #[test]
+    fn workspace_should_list_and_switch_workspaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(
+            &path,
+            r#"{"workspace": "a", "workspaces": {"a": {"path": "/db/a.json"}, "b": {"path": "/db/b", "backend": "directory"}}}"#,
+        )
+        .unwrap();
+        let mut files = ConfigFiles::load(Some(path.clone()), None, None).unwrap();
+
+        let workspace_to_string = |files: &mut ConfigFiles, args: &[&str]| -> Result<String> {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            let mut out = vec![];
+            workspace(files, &args, &mut out)?;
+            Ok(String::from_utf8(out).unwrap())
+        };
+
+        let output = workspace_to_string(&mut files, &["list"]).unwrap();
+        assert_eq!(output, "* a | json | /db/a.json\n  b | directory | /db/b\n");
+
+        let output = workspace_to_string(&mut files, &["switch", "b"]).unwrap();
+        assert_eq!(output, "Switched to b\n");
+        assert_eq!(workspace_to_string(&mut files, &[]).unwrap(), "  a | json | /db/a.json\n* b | directory | /db/b\n");
+
+        files.env_workspace = Some("b".to_owned());
+        let output = workspace_to_string(&mut files, &["switch", "a"]).unwrap();
+        assert_eq!(output, "Switched to a\nJIRA_WORKSPACE is set to b, which is used instead while it is set\n");
+
+        assert!(workspace_to_string(&mut files, &["switch", "c"]).is_err());
+        assert!(workspace_to_string(&mut files, &["remove", "a"]).is_err());
+    }
+
+    #[test]
+    fn webhooks_should_list_and_retry_pending_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert!(run_to_string(&db, &["webhooks", "retry"]).is_err());
+
+        //nothing listens on port 9 on localhost, so the events stay queued
+        let webhook = crate::webhooks::Webhook {
+            url: "http://127.0.0.1:9/hook".to_owned(),
+            events: vec![],
+        };
+        let db = db.with_webhooks(vec![webhook], dir.path().join("webhooks.json"));
+        assert_eq!(run_to_string(&db, &["webhooks"]).unwrap(), "No webhook events are waiting to be sent\n");
+
+        db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+
+        let output = run_to_string(&db, &["webhooks"]).unwrap();
+        assert!(output.starts_with("epic.created to http://127.0.0.1:9/hook | 1 attempts | next "));
+
+        let output = run_to_string(&db, &["webhooks", "retry"]).unwrap();
+        assert_eq!(output, "Sent 0 webhook events, 1 still waiting\n");
+    }
+
+    #[test]
+    fn rules_dry_run_should_show_what_the_rules_would_change_without_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("follow_epic.rhai");
+        fs::write(&script, "for story in epic(event.item.key).stories { set_story_status(story.key, event[\"new\"]); }").unwrap();
+        let rule = crate::rules::Rule {
+            event: "epic.status_changed".to_owned(),
+            script: script.clone(),
+            time_limit_ms: None,
+        };
+
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        let story_id = db.create_story(Story::new("Login".to_owned(), "".to_owned()), epic_id).unwrap();
+        db.update_story_status(story_id, Status::InProgress).unwrap();
+        db.update_epic_status(epic_id, Status::Closed).unwrap();
+        let db = db.with_rules(vec![rule]);
+
+        assert_eq!(run_to_string(&db, &["rules"]).unwrap(), format!("epic.status_changed | {} | 250ms\n", script.display()));
+
+        let output = run_to_string(&db, &["rules", "dry-run", "undo"]).unwrap();
+        assert_eq!(
+            output,
+            "Undone: set status of epic 1 to OPEN\n\
+             Rules would make story.status_changed 2: \"InProgress\" -> \"Open\"\n\
+             Nothing was saved\n"
+        );
+        assert_eq!(db.read_db().unwrap().epics[&epic_id].status, Status::Closed);
+        assert_eq!(db.read_db().unwrap().stories[&story_id].status, Status::InProgress);
+
+        assert!(run_to_string(&db, &["rules", "dry-run", "merge", "a", "b", "c"]).is_err());
+
+        //commands that write files other than the database aren't run at all
+        let output = dir.path().join("stories.csv");
+        let output = output.to_str().unwrap();
+        let error = run_to_string(&db, &["rules", "dry-run", "export", "csv", "--output", output]).unwrap_err();
+        assert_eq!(error.to_string(), format!("export csv --output {output} can't be dry run"));
+        assert!(!Path::new(output).exists());
+        assert!(run_to_string(&db, &["rules", "dry-run", "git", "install-hooks", dir.path().to_str().unwrap()]).is_err());
+        assert!(run_to_string(&db, &["rules", "dry-run", "import", "jira", "export.csv", "--ids", "ids.json"]).is_err());
+    }
+
+    #[test]
+    fn import_should_save_the_id_mapping_and_skip_known_issues_next_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = dir.path().join("export.csv");
+        let ids = dir.path().join("ids.json");
+        fs::write(&export, "Issue key,Issue Type,Summary,Status,Parent\nA-1,Epic,Accounts,Review,\nA-2,Story,Login,Done,A-1\n").unwrap();
+        let (export, ids) = (export.to_str().unwrap(), ids.to_str().unwrap());
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        let output = run_to_string(&db, &["import", "jira", export, "--ids", ids]).unwrap();
+        assert_eq!(
+            output,
+            "Problem: A-1 has the status \"Review\", which has to be mapped with --status\nNothing was imported because of the problems above\n"
+        );
+
+        let output = run_to_string(&db, &["import", "jira", export, "--status", "Review=InProgress", "--dry-run"]).unwrap();
+        assert_eq!(output, "A-1 -> 1\nA-2 -> 2\nWould import 1 epics and 1 stories, nothing was saved\n");
+        assert!(db.read_db().unwrap().epics.is_empty());
+
+        let output = run_to_string(&db, &["import", "jira", export, "--status", "Review=InProgress", "--ids", ids]).unwrap();
+        assert_eq!(output, format!("Imported 1 epics and 1 stories\nSaved the id mapping in {ids}\n"));
+        assert_eq!(fs::read_to_string(ids).unwrap(), "{\n  \"A-1\": \"1\",\n  \"A-2\": \"2\"\n}\n");
+
+        let output = run_to_string(&db, &["import", "jira", export, "--status", "Review=InProgress", "--ids", ids]).unwrap();
+        assert!(output.starts_with("Imported 0 epics and 0 stories\n"));
+        assert!(run_to_string(&db, &["import", "jira", export, "--status"]).is_err());
+    }
+
+    #[test]
+    fn import_markdown_again_should_only_update_what_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tasks = dir.path().join("tasks.md");
+        fs::write(&tasks, "## Accounts\n- [ ] Login\n- [ ] Logout\n").unwrap();
+        let tasks = tasks.to_str().unwrap();
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert_eq!(run_to_string(&db, &["import", "markdown", tasks]).unwrap(), "Imported 1 epics and 2 stories\n");
+
+        fs::write(tasks, "## Accounts\n- [x] Login\n- [ ] Logout\n- [ ] Sign up\n").unwrap();
+        let output = run_to_string(&db, &["import", "md", tasks, "--dry-run"]).unwrap();
+        assert_eq!(output, "Would import 0 epics and 1 stories and update the status of 1 items imported before, nothing was saved\n");
+        let output = run_to_string(&db, &["import", "md", tasks]).unwrap();
+        assert_eq!(output, "Imported 0 epics and 1 stories and updated the status of 1 items imported before\n");
+        assert_eq!(db.read_db().unwrap().stories[&2].status, Status::Closed);
+        assert!(run_to_string(&db, &["import", "trello", tasks]).is_err());
+    }
+
+    #[test]
+    fn export_should_write_what_a_query_or_filter_finds() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login".to_owned(), "".to_owned()), epic_id).unwrap();
+        let logout = db.create_story(Story::new("Logout".to_owned(), "".to_owned()), epic_id).unwrap();
+        db.update_story_status(logout, Status::Closed).unwrap();
+        db.save_filter("done", "status = closed").unwrap();
+
+        let output = run_to_string(&db, &["export", "markdown", "status", "=", "open"]).unwrap();
+        assert_eq!(output, "# Stories matching status = open\n\n## 1 Accounts (OPEN)\n\n- [ ] 2 Login (OPEN)\n");
+
+        let path = dir.path().join("done.csv");
+        let output = run_to_string(&db, &["export", "csv", "--filter", "done", "--output", path.to_str().unwrap()]).unwrap();
+        assert_eq!(output, format!("Exported 1 stories to {}\n", path.display()));
+        assert!(fs::read_to_string(&path).unwrap().ends_with("\n1,Accounts,OPEN,3,Logout,CLOSED,,,\n"));
+
+        assert_eq!(run_to_string(&db, &["export", "md"]).unwrap().lines().count(), 6);
+        assert!(run_to_string(&db, &["export", "pdf"]).is_err());
+        assert!(run_to_string(&db, &["export", "csv", "--filter", "done", "status = open"]).is_err());
+    }
+
+    #[test]
+    fn serve_should_error_for_a_bad_port() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert!(run_to_string(&db, &["serve", "http"]).is_err());
+        assert!(run_to_string(&db, &["serve", "80", "81"]).is_err());
+    }
+
+    #[test]
+    fn undo_and_redo_should_print_what_was_done() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        db.update_epic_status(epic_id, Status::Closed).unwrap();
+
+        let output = run_to_string(&db, &["undo"]).unwrap();
+        assert_eq!(output, "Undone: set status of epic 1 to OPEN\n");
+
+        let output = run_to_string(&db, &["redo"]).unwrap();
+        assert_eq!(output, "Redone: set status of epic 1 to CLOSED\n");
+
+        assert!(run_to_string(&db, &["redo"]).is_err());
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::{to_stable_json, JiraDatabase};
+use crate::hooks::Hook;
+use crate::models::BackupConfig;
+use crate::rules::Rule;
+use crate::webhooks::Webhook;
+
+// the file a repository can check in next to its code, found by looking upwards from the current directory
+pub const REPO_CONFIG_FILE: &str = ".jira.json";
+// names the workspace to use, over whatever the config files say
+pub const WORKSPACE_ENV_VAR: &str = "JIRA_WORKSPACE";
+
+// the default board column width, not counting the separators between columns
+pub const DEFAULT_BOARD_COLUMN_WIDTH: usize = 24;
+
+// where the database was before there were config files, and still is when no workspace is configured
+const LEGACY_EVENT_LOG_PATH: &str = "./data/db.events.jsonl";
+const LEGACY_DIRECTORY_PATH: &str = "./data/db";
+const LEGACY_JSON_PATH: &str = "./data/db.json";
+const LEGACY_WEBHOOK_QUEUE_PATH: &str = "./data/webhooks.json";
+
+// the contents of a config file
+// a repo config is laid over the user config, so every field is optional and the workspaces of both are combined
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Config {
+    // the name of the active workspace
+    #[serde(default)]
+    pub workspace: Option<String>,
+    #[serde(default)]
+    pub workspaces: BTreeMap<String, Workspace>,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    // where to send changes to, for every workspace
+    // only the user config's are used, like hooks
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    // programs to run before and after changes, for every workspace
+    // only the user config's are used, see ConfigFiles::load
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    // scripts that make changes of their own when something changes, for every workspace
+    // only the user config's are used, like hooks
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+// a database somewhere on disk and how it is stored
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Workspace {
+    // relative paths are relative to the config file the workspace is in
+    pub path: PathBuf,
+    #[serde(default)]
+    pub backend: Backend,
+    // the backups and pretty printing only apply to the json backend
+    #[serde(default)]
+    pub backups: BackupSettings,
+    // pretty printed with sorted keys, for a file that is committed to git
+    #[serde(default = "default_pretty")]
+    pub pretty: bool,
+}
+
+fn default_pretty() -> bool {
+    true
+}
+
+// how many copies of the previous db file are kept, null for either limit means there is no limit
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct BackupSettings {
+    #[serde(default = "default_backups_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_backup_keep")]
+    pub keep: Option<usize>,
+    #[serde(default = "default_backup_max_age_days")]
+    pub max_age_days: Option<u32>,
+}
+
+fn default_backups_enabled() -> bool {
+    true
+}
+
+fn default_backup_keep() -> Option<usize> {
+    BackupConfig::default().keep
+}
+
+fn default_backup_max_age_days() -> Option<u32> {
+    BackupConfig::default().max_age_days
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            enabled: default_backups_enabled(),
+            keep: default_backup_keep(),
+            max_age_days: default_backup_max_age_days(),
+        }
+    }
+}
+
+impl BackupSettings {
+    // what the database is given, None turns backups off
+    pub fn backup_config(&self) -> Option<BackupConfig> {
+        self.enabled.then_some(BackupConfig {
+            keep: self.keep,
+            max_age_days: self.max_age_days,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    #[default]
+    Json,
+    EventLog,
+    Directory,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Json => write!(f, "json"),
+            Backend::EventLog => write!(f, "event_log"),
+            Backend::Directory => write!(f, "directory"),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DisplayConfig {
+    #[serde(default)]
+    pub board_column_width: Option<usize>,
+    // whether the terminal is cleared before each page is drawn
+    #[serde(default)]
+    pub clear_screen: Option<bool>,
+}
+
+impl DisplayConfig {
+    pub fn board_column_width(&self) -> usize {
+        self.board_column_width.unwrap_or(DEFAULT_BOARD_COLUMN_WIDTH)
+    }
+
+    pub fn clear_screen(&self) -> bool {
+        self.clear_screen.unwrap_or(true)
+    }
+}
+
+impl Workspace {
+    // a workspace with the default settings for its backend
+    pub fn new(path: PathBuf, backend: Backend) -> Self {
+        Workspace {
+            path,
+            backend,
+            backups: BackupSettings::default(),
+            pretty: default_pretty(),
+        }
+    }
+
+    pub fn open(&self) -> JiraDatabase {
+        let path = self.path.to_string_lossy().into_owned();
+
+        match self.backend {
+            Backend::Json => JiraDatabase::with_options(path, self.backups.backup_config(), self.pretty),
+            Backend::EventLog => JiraDatabase::with_event_log(path),
+            Backend::Directory => JiraDatabase::with_directory(path),
+        }
+    }
+
+    // the queue of webhook events sits next to the database, so db.json and db.events.jsonl both get db.webhooks.json
+    // the rest of the name is kept, so team.a.json and team.b.json don't share a queue
+    pub fn webhook_queue_path(&self) -> PathBuf {
+        let file_stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let stem = file_stem.strip_suffix(".events").unwrap_or(&file_stem);
+        self.path.with_file_name(format!("{stem}.webhooks.json"))
+    }
+}
+
+// the user config and the repo config, if they exist, along with the settings they add up to
+pub struct ConfigFiles {
+    pub user_path: Option<PathBuf>,
+    pub repo_path: Option<PathBuf>,
+    // the workspace named by the environment variable, if it was set
+    pub env_workspace: Option<String>,
+    pub config: Config,
+}
+
+impl ConfigFiles {
+    // reads both config files, either of which may be missing, and lays the repo config over the user config
+    // the webhooks, hooks and rules of a repo config are left out, since a repo config comes with whatever repository was
+    // cloned, and running the program inside it shouldn't send changes anywhere or run anything that the repository chose
+    pub fn load(user_path: Option<PathBuf>, repo_path: Option<PathBuf>, env_workspace: Option<String>) -> Result<Self> {
+        let mut config = Config::default();
+
+        if let Some(user_config) = user_path.as_deref().map(read_config).transpose()?.flatten() {
+            config.layer(user_config);
+        }
+        if let Some(mut repo_config) = repo_path.as_deref().map(read_config).transpose()?.flatten() {
+            repo_config.webhooks.clear();
+            repo_config.hooks.clear();
+            repo_config.rules.clear();
+            config.layer(repo_config);
+        }
+
+        Ok(ConfigFiles {
+            user_path,
+            repo_path,
+            env_workspace,
+            config,
+        })
+    }
+
+    // loads the config files from their usual places
+    pub fn from_environment() -> Result<Self> {
+        let repo_path = std::env::current_dir().ok().and_then(|dir| find_repo_config(&dir));
+        let env_workspace = std::env::var(WORKSPACE_ENV_VAR).ok().filter(|name| !name.is_empty());
+        Self::load(user_config_path(), repo_path, env_workspace)
+    }
+
+    // the name of the workspace in use, the environment variable winning over the config files
+    pub fn active_name(&self) -> Option<&String> {
+        self.env_workspace.as_ref().or(self.config.workspace.as_ref())
+    }
+
+    pub fn active_workspace(&self) -> Result<Option<&Workspace>> {
+        let Some(name) = self.active_name() else {
+            return Ok(None);
+        };
+
+        match self.config.workspaces.get(name) {
+            Some(workspace) => Ok(Some(workspace)),
+            None => Err(anyhow!("There is no workspace called {name} in the config")),
+        }
+    }
+
+    // opens the active workspace, or the database in ./data when there isn't one
+    pub fn open_database(&self) -> Result<JiraDatabase> {
+        let (db, queue_path) = match self.active_workspace()? {
+            Some(workspace) => (workspace.open(), workspace.webhook_queue_path()),
+            None => (Self::open_legacy_database(), PathBuf::from(LEGACY_WEBHOOK_QUEUE_PATH)),
+        };
+
+        //webhooks go on the inside, so that a change a pre hook stops is never sent anywhere
+        let db = match self.config.webhooks.is_empty() {
+            true => db,
+            false => db.with_webhooks(self.config.webhooks.clone(), queue_path),
+        };
+        let db = match self.config.hooks.is_empty() {
+            true => db,
+            false => db.with_hooks(self.config.hooks.clone()),
+        };
+        //rules go on the outside, so that the changes they make are checked by the pre hooks and sent to the webhooks too
+        let db = match self.config.rules.is_empty() {
+            true => db,
+            false => db.with_rules(self.config.rules.clone()),
+        };
+        Ok(db)
+    }
+
+    // the legacy database has the default settings, configuring a workspace for it is how they are changed
+    fn open_legacy_database() -> JiraDatabase {
+        //once an event log or a directory has been started with `log init` or `directory init` it is used instead of the json file
+        let workspace = if Path::new(LEGACY_EVENT_LOG_PATH).exists() {
+            Workspace::new(PathBuf::from(LEGACY_EVENT_LOG_PATH), Backend::EventLog)
+        } else if Path::new(LEGACY_DIRECTORY_PATH).is_dir() {
+            Workspace::new(PathBuf::from(LEGACY_DIRECTORY_PATH), Backend::Directory)
+        } else {
+            Workspace::new(PathBuf::from(LEGACY_JSON_PATH), Backend::Json)
+        };
+        workspace.open()
+    }
+
+    // makes a workspace the active one from now on
+    // it is saved in the repo config when there is one, since that would override the user config anyway
+    pub fn switch_workspace(&mut self, name: &str) -> Result<()> {
+        if !self.config.workspaces.contains_key(name) {
+            return Err(anyhow!("There is no workspace called {name} in the config"));
+        }
+
+        let path = self
+            .repo_path
+            .as_ref()
+            .or(self.user_path.as_ref())
+            .ok_or_else(|| anyhow!("There is no config file to save the workspace in"))?;
+
+        //the file is edited as plain json so that nothing else in it changes, including relative paths
+        let mut contents = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).with_context(|| format!("{} is not valid json", path.display()))?,
+            Err(_) => Value::Object(Default::default()),
+        };
+        let Value::Object(fields) = &mut contents else {
+            return Err(anyhow!("{} doesn't hold a json object", path.display()));
+        };
+        fields.insert("workspace".to_owned(), Value::String(name.to_owned()));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, to_stable_json(&contents)?)?;
+
+        self.config.workspace = Some(name.to_owned());
+        Ok(())
+    }
+}
+
+impl Config {
+    // replaces every setting that the other config has
+    fn layer(&mut self, other: Config) {
+        if other.workspace.is_some() {
+            self.workspace = other.workspace;
+        }
+        self.workspaces.extend(other.workspaces);
+        self.webhooks.extend(other.webhooks);
+        self.hooks.extend(other.hooks);
+        self.rules.extend(other.rules);
+        if other.display.board_column_width.is_some() {
+            self.display.board_column_width = other.display.board_column_width;
+        }
+        if other.display.clear_screen.is_some() {
+            self.display.clear_screen = other.display.clear_screen;
+        }
+    }
+}
+
+// reads a config file, making the workspace paths in it relative to the file instead of the current directory
+fn read_config(path: &Path) -> Result<Option<Config>> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Ok(None);
+    };
+
+    let mut config: Config =
+        serde_json::from_str(&contents).with_context(|| format!("{} is not a valid config file", path.display()))?;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for workspace in config.workspaces.values_mut() {
+        if workspace.path.is_relative() {
+            workspace.path = dir.join(&workspace.path);
+        }
+    }
+    for hook in config.hooks.iter_mut() {
+        if hook.command.contains('/') && Path::new(&hook.command).is_relative() {
+            hook.command = dir.join(&hook.command).to_string_lossy().into_owned();
+        }
+    }
+    for rule in config.rules.iter_mut() {
+        if rule.script.is_relative() {
+            rule.script = dir.join(&rule.script);
+        }
+    }
+
+    Ok(Some(config))
+}
+
+// $XDG_CONFIG_HOME/jira-clone/config.json, falling back to ~/.config like most tools do
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("jira-clone").join("config.json"))
+}
+
+// the closest .jira.json in the given directory or one of its parents
+pub fn find_repo_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|dir| dir.join(REPO_CONFIG_FILE)).find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn load_should_lay_the_repo_config_over_the_user_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_path = dir.path().join("user/config.json");
+        let repo_path = dir.path().join("repo/.jira.json");
+        write(
+            &user_path,
+            r#"{"workspace": "personal", "workspaces": {"personal": {"path": "/home/sam/db.json"}}, "display": {"board_column_width": 30, "clear_screen": false}}"#,
+        );
+        write(
+            &repo_path,
+            r#"{"workspace": "team", "workspaces": {"team": {"path": "data/db", "backend": "directory"}}, "display": {"board_column_width": 20}}"#,
+        );
+
+        let files = ConfigFiles::load(Some(user_path), Some(repo_path), None).unwrap();
+
+        assert_eq!(files.active_name(), Some(&"team".to_owned()));
+        assert_eq!(files.config.workspaces.len(), 2);
+        assert_eq!(
+            files.active_workspace().unwrap(),
+            Some(&Workspace::new(dir.path().join("repo/data/db"), Backend::Directory))
+        );
+        assert_eq!(files.config.display.board_column_width(), 20);
+        assert!(!files.config.display.clear_screen());
+    }
+
+    #[test]
+    fn load_should_allow_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = ConfigFiles::load(Some(dir.path().join("config.json")), None, None).unwrap();
+
+        assert_eq!(files.config, Config::default());
+        assert_eq!(files.active_workspace().unwrap(), None);
+        assert_eq!(files.config.display.board_column_width(), DEFAULT_BOARD_COLUMN_WIDTH);
+    }
+
+    #[test]
+    fn load_should_error_on_an_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        write(&path, r#"{"workspaces": {"team": {"backend": "sqlite"}}}"#);
+
+        assert!(ConfigFiles::load(Some(path), None, None).is_err());
+    }
+
+    #[test]
+    fn env_workspace_should_override_the_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        write(
+            &path,
+            r#"{"workspace": "a", "workspaces": {"a": {"path": "a.json"}, "b": {"path": "b.json"}}}"#,
+        );
+
+        let files = ConfigFiles::load(Some(path.clone()), None, Some("b".to_owned())).unwrap();
+        assert_eq!(files.active_workspace().unwrap().unwrap().path, dir.path().join("b.json"));
+
+        let files = ConfigFiles::load(Some(path), None, Some("c".to_owned())).unwrap();
+        assert!(files.active_workspace().is_err());
+        assert!(files.open_database().is_err());
+    }
+
+    #[test]
+    fn switch_workspace_should_save_to_the_repo_config_when_there_is_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_path = dir.path().join("user/config.json");
+        let repo_path = dir.path().join("repo/.jira.json");
+        write(&user_path, r#"{"workspaces": {"personal": {"path": "db.json"}}}"#);
+        write(&repo_path, r#"{"workspaces": {"team": {"path": "db.json"}}}"#);
+
+        let mut files = ConfigFiles::load(Some(user_path.clone()), Some(repo_path.clone()), None).unwrap();
+        files.switch_workspace("personal").unwrap();
+        assert!(files.switch_workspace("nope").is_err());
+
+        let files = ConfigFiles::load(Some(user_path.clone()), Some(repo_path.clone()), None).unwrap();
+        assert_eq!(files.active_name(), Some(&"personal".to_owned()));
+        //the relative path in the file is left as it was
+        assert!(fs::read_to_string(&repo_path).unwrap().contains("\"path\": \"db.json\""));
+        assert!(!fs::read_to_string(&user_path).unwrap().contains("\"workspace\":"));
+    }
+
+    #[test]
+    fn switch_workspace_should_create_the_user_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = ConfigFiles::load(Some(dir.path().join("jira-clone/config.json")), None, None).unwrap();
+        files.config.workspaces.insert(
+            "team".to_owned(),
+            Workspace::new(PathBuf::from("db.json"), Backend::Json),
+        );
+
+        files.switch_workspace("team").unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("jira-clone/config.json")).unwrap(),
+            "{\n  \"workspace\": \"team\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn webhook_queue_should_sit_next_to_the_database() {
+        let workspace = |path: &str| Workspace::new(PathBuf::from(path), Backend::Json);
+
+        assert_eq!(workspace("/data/db.json").webhook_queue_path(), PathBuf::from("/data/db.webhooks.json"));
+        assert_eq!(workspace("/data/db.events.jsonl").webhook_queue_path(), PathBuf::from("/data/db.webhooks.json"));
+        assert_eq!(workspace("/data/db").webhook_queue_path(), PathBuf::from("/data/db.webhooks.json"));
+        assert_eq!(workspace("/data/team.a.json").webhook_queue_path(), PathBuf::from("/data/team.a.webhooks.json"));
+        assert_eq!(workspace("/data/team.b.json").webhook_queue_path(), PathBuf::from("/data/team.b.webhooks.json"));
+    }
+
+    #[test]
+    fn load_should_only_use_the_webhooks_of_the_user_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_path = dir.path().join("user/config.json");
+        let repo_path = dir.path().join("repo/.jira.json");
+        write(&user_path, r#"{"webhooks": [{"url": "http://localhost:9000/mine"}]}"#);
+        write(
+            &repo_path,
+            r#"{"webhooks": [{"url": "http://localhost:9000/team", "events": ["story.status_changed"]}]}"#,
+        );
+
+        let files = ConfigFiles::load(Some(user_path), Some(repo_path), None).unwrap();
+
+        assert_eq!(files.config.webhooks.len(), 1);
+        assert_eq!(files.config.webhooks[0].url, "http://localhost:9000/mine");
+    }
+
+    #[test]
+    fn hook_commands_with_a_path_should_be_relative_to_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user/config.json");
+        write(
+            &path,
+            r#"{"hooks": [
+                {"event": "story.status_changed", "stage": "pre", "command": "./hooks/check.sh"},
+                {"event": "*", "stage": "post", "command": "notify-send", "args": ["changed"]}
+            ]}"#,
+        );
+
+        let files = ConfigFiles::load(Some(path), None, None).unwrap();
+
+        assert_eq!(files.config.hooks[0].command, dir.path().join("user/./hooks/check.sh").to_string_lossy());
+        assert_eq!(files.config.hooks[1].command, "notify-send");
+        assert_eq!(files.config.hooks[1].args, vec!["changed".to_owned()]);
+    }
+
+    #[test]
+    fn rule_scripts_should_be_relative_to_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user/config.json");
+        write(&path, r#"{"rules": [{"event": "story.status_changed", "script": "rules/close_epic.rhai", "time_limit_ms": 50}]}"#);
+
+        let files = ConfigFiles::load(Some(path), None, None).unwrap();
+
+        assert_eq!(files.config.rules[0].script, dir.path().join("user/rules/close_epic.rhai"));
+        assert_eq!(files.config.rules[0].time_limit_ms, Some(50));
+    }
+
+    #[test]
+    fn load_should_ignore_the_hooks_and_rules_of_a_repo_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_path = dir.path().join("user/config.json");
+        let repo_path = dir.path().join("repo/.jira.json");
+        write(&user_path, r#"{"hooks": [{"event": "*", "stage": "post", "command": "notify-send"}]}"#);
+        write(
+            &repo_path,
+            r#"{
+                "hooks": [{"event": "*", "stage": "pre", "command": "./steal.sh"}],
+                "rules": [{"event": "*", "script": "rules/anything.rhai"}],
+                "display": {"clear_screen": false}
+            }"#,
+        );
+
+        let files = ConfigFiles::load(Some(user_path), Some(repo_path), None).unwrap();
+
+        assert_eq!(files.config.hooks.len(), 1);
+        assert_eq!(files.config.hooks[0].command, "notify-send");
+        assert!(files.config.rules.is_empty());
+        //the rest of the repo config still applies
+        assert!(!files.config.display.clear_screen());
+    }
+
+    #[test]
+    fn find_repo_config_should_look_in_parent_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join(REPO_CONFIG_FILE), "{}");
+        fs::create_dir_all(dir.path().join("src/ui")).unwrap();
+
+        assert_eq!(find_repo_config(&dir.path().join("src/ui")), Some(dir.path().join(REPO_CONFIG_FILE)));
+    }
+
+    #[test]
+    fn workspace_should_open_its_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path().join("db"), Backend::Directory);
+        crate::directory::DirectoryDatabase::create(workspace.path.clone(), &crate::models::DBState::default()).unwrap();
+
+        let db = workspace.open();
+        db.create_epic(crate::models::Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+        assert!(dir.path().join("db/epics/1.json").is_file());
+    }
+
+    #[test]
+    fn workspace_should_read_its_backup_settings() {
+        let config: Config = serde_json::from_str(
+            r#"{"workspaces": {
+                "kept": {"path": "kept.json", "backups": {"keep": 3, "max_age_days": null}, "pretty": false},
+                "off": {"path": "off.json", "backups": {"enabled": false}},
+                "default": {"path": "default.json"}
+            }}"#,
+        )
+        .unwrap();
+
+        let kept = &config.workspaces["kept"];
+        assert_eq!(kept.backups.backup_config(), Some(BackupConfig { keep: Some(3), max_age_days: None }));
+        assert!(!kept.pretty);
+        assert_eq!(config.workspaces["off"].backups.backup_config(), None);
+        assert_eq!(config.workspaces["default"].backups.backup_config(), Some(BackupConfig::default()));
+        assert!(config.workspaces["default"].pretty);
+    }
+
+    #[test]
+    fn workspace_with_backups_turned_off_should_not_back_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut workspace = Workspace::new(dir.path().join("db.json"), Backend::Json);
+        workspace.backups.enabled = false;
+        fs::write(&workspace.path, r#"{ "last_item_id": 0, "epics": {}, "stories": {} }"#).unwrap();
+
+        let db = workspace.open();
+        db.create_epic(crate::models::Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+        assert!(db.list_backups().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::db::{to_stable_json, Database};
+use crate::models::DBState;
+
+// the fields of DBState that get a directory with one file per item, so that edits to different items touch different files
+const ITEM_DIRS: [&str; 3] = ["epics", "stories", "sprints"];
+// the fields that only make sense for one copy of the database, kept in a directory that git ignores
+// the undo history is about the changes made in this copy, and every change adds to it, so it would conflict on every merge
+const LOCAL_FIELDS: [&str; 1] = ["history"];
+const LOCAL_DIR: &str = ".local";
+
+const GITIGNORE: &str = ".local/\n";
+// the rest of the shared files are merged as json, see merge::merge_file, once the driver is registered with
+//   git config merge.jira-json.driver "p01-jira-clone merge-file %O %A %B %P"
+const GITATTRIBUTES: &str = "*.json merge=jira-json\n";
+
+// a Database that spreads the state over a directory, for keeping it in git:
+//   epics/<id>.json, stories/<id>.json and sprints/<id>.json hold one item each
+//   every other field of DBState is in <field>.json, except the undo history which is in .local/history.json
+//   archive/ holds the archive in the same layout
+// every file is pretty printed with sorted keys and only rewritten when its contents change
+// copies that create items at the same time need site or time ids: with sequential ids both would write the same
+// epics/<id>.json, and git merges projects.json and keys.json on their own, so two items given the same key number
+// are left as a conflict
+pub(crate) struct DirectoryDatabase {
+    pub dir: PathBuf,
+}
+
+impl DirectoryDatabase {
+    // writes the given state into a new directory, refusing to use one that already has files in it
+    pub fn create(dir: PathBuf, db_state: &DBState) -> Result<Self> {
+        if dir.exists() && fs::read_dir(&dir)?.next().is_some() {
+            return Err(anyhow!("{} already exists and isn't empty", dir.display()));
+        }
+
+        let db = DirectoryDatabase { dir };
+        db.write_db(db_state)?;
+        Ok(db)
+    }
+
+    fn archive(&self) -> DirectoryDatabase {
+        DirectoryDatabase {
+            dir: self.dir.join("archive"),
+        }
+    }
+}
+
+impl Database for DirectoryDatabase {
+    fn read_db(&self) -> Result<DBState> {
+        if !self.dir.is_dir() {
+            return Err(anyhow!("{} is not a directory", self.dir.display()));
+        }
+
+        let mut state = Map::new();
+
+        //directories written before the history moved to .local have it next to everything else, so it is read here too
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(field) = json_file_stem(&path) else {
+                continue;
+            };
+            state.insert(field, read_json(&path)?);
+        }
+
+        //git doesn't keep empty directories, so a missing item directory just means there are no items
+        for field in ITEM_DIRS {
+            let mut items = Map::new();
+            let item_dir = self.dir.join(field);
+
+            if item_dir.is_dir() {
+                for entry in fs::read_dir(&item_dir)? {
+                    let path = entry?.path();
+                    let Some(id) = json_file_stem(&path) else {
+                        continue;
+                    };
+                    items.insert(id, read_json(&path)?);
+                }
+            }
+
+            state.insert(field.to_owned(), Value::Object(items));
+        }
+
+        //the copy in .local wins over one left behind by an older version
+        for field in LOCAL_FIELDS {
+            let path = self.dir.join(LOCAL_DIR).join(format!("{field}.json"));
+            if path.is_file() {
+                state.insert(field.to_owned(), read_json(&path)?);
+            }
+        }
+
+        serde_json::from_value(Value::Object(state))
+            .with_context(|| format!("{} doesn't hold a valid database", self.dir.display()))
+    }
+
+    fn write_db(&self, db_state: &DBState) -> Result<()> {
+        let Value::Object(state) = serde_json::to_value(db_state)? else {
+            return Err(anyhow!("The database didn't serialize to an object"));
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        write_text_if_changed(&self.dir.join(".gitignore"), GITIGNORE)?;
+        write_text_if_changed(&self.dir.join(".gitattributes"), GITATTRIBUTES)?;
+
+        for (field, value) in state {
+            if LOCAL_FIELDS.contains(&field.as_str()) {
+                fs::create_dir_all(self.dir.join(LOCAL_DIR))?;
+                write_if_changed(&self.dir.join(LOCAL_DIR).join(format!("{field}.json")), &value)?;
+                let shared = self.dir.join(format!("{field}.json"));
+                if shared.exists() {
+                    fs::remove_file(shared)?;
+                }
+                continue;
+            }
+
+            if !ITEM_DIRS.contains(&field.as_str()) {
+                write_if_changed(&self.dir.join(format!("{field}.json")), &value)?;
+                continue;
+            }
+
+            let item_dir = self.dir.join(&field);
+            fs::create_dir_all(&item_dir)?;
+
+            let items = value.as_object().cloned().unwrap_or_default();
+            for (id, item) in &items {
+                write_if_changed(&item_dir.join(format!("{id}.json")), item)?;
+            }
+
+            //anything that is no longer in the state has been removed
+            for entry in fs::read_dir(&item_dir)? {
+                let path = entry?.path();
+                if json_file_stem(&path).is_some_and(|id| !items.contains_key(&id)) {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_archive(&self) -> Result<DBState> {
+        let archive = self.archive();
+
+        if !archive.dir.exists() {
+            return Ok(DBState::default());
+        }
+
+        archive.read_db()
+    }
+
+    fn write_archive(&self, archive: &DBState) -> Result<()> {
+        self.archive().write_db(archive)
+    }
+}
+
+// the name of a .json file without the extension, or None for anything else
+fn json_file_stem(path: &Path) -> Option<String> {
+    if !path.is_file() || path.extension()? != "json" {
+        return None;
+    }
+    Some(path.file_stem()?.to_string_lossy().into_owned())
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).with_context(|| format!("{} is not valid json", path.display()))
+}
+
+// leaving files that haven't changed alone keeps their timestamps, and the git status, quiet
+fn write_if_changed(path: &Path, value: &Value) -> Result<()> {
+    write_text_if_changed(path, &to_stable_json(value)?)
+}
+
+fn write_text_if_changed(path: &Path, contents: &str) -> Result<()> {
+    if fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Epic, Operation, Story};
+
+    fn setup() -> DBState {
+        let mut db_state = DBState {
+            last_item_id: 2,
+            ..Default::default()
+        };
+        db_state.epics.insert(1, Epic::new("Accounts".to_owned(), "".to_owned()));
+        db_state.stories.insert(2, Story::new("Login page".to_owned(), "".to_owned()));
+        db_state.epics.get_mut(&1).unwrap().stories.push(2);
+        db_state
+    }
+
+    #[test]
+    fn create_should_error_if_directory_has_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        assert!(DirectoryDatabase::create(dir.path().to_owned(), &DBState::default()).is_err());
+    }
+
+    #[test]
+    fn write_db_should_write_one_file_per_item() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DirectoryDatabase::create(dir.path().join("db"), &setup()).unwrap();
+
+        assert!(db.dir.join("epics/1.json").is_file());
+        assert!(db.dir.join("stories/2.json").is_file());
+        assert!(db.dir.join("last_item_id.json").is_file());
+        assert_eq!(db.read_db().unwrap(), setup());
+
+        let story = fs::read_to_string(db.dir.join("stories/2.json")).unwrap();
+        assert!(story.starts_with("{\n  \"assignee\": null,\n  \"commits\": [],\n  \"description\": \"\",\n"));
+    }
+
+    #[test]
+    fn write_db_should_remove_deleted_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DirectoryDatabase::create(dir.path().join("db"), &setup()).unwrap();
+
+        let mut db_state = setup();
+        db_state.stories.remove(&2);
+        db_state.epics.get_mut(&1).unwrap().stories.clear();
+        db.write_db(&db_state).unwrap();
+
+        assert!(!db.dir.join("stories/2.json").exists());
+        assert_eq!(db.read_db().unwrap(), db_state);
+    }
+
+    #[test]
+    fn read_db_should_treat_missing_item_directories_as_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DirectoryDatabase::create(dir.path().join("db"), &setup()).unwrap();
+        fs::remove_dir_all(db.dir.join("stories")).unwrap();
+
+        assert!(db.read_db().unwrap().stories.is_empty());
+    }
+
+    #[test]
+    fn history_should_be_kept_out_of_git() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db_state = setup();
+        db_state.history.undo.push(Operation::RemoveEpic { epic_id: 1 });
+        let db = DirectoryDatabase::create(dir.path().join("db"), &setup()).unwrap();
+
+        //a directory from before the history moved is read as it is, and moved over on the next write
+        fs::remove_dir_all(db.dir.join(".local")).unwrap();
+        fs::write(db.dir.join("history.json"), to_stable_json(&db_state.history).unwrap()).unwrap();
+        assert_eq!(db.read_db().unwrap().history, db_state.history);
+        db.write_db(&db_state).unwrap();
+
+        assert!(!db.dir.join("history.json").exists());
+        assert!(db.dir.join(".local/history.json").is_file());
+        assert_eq!(fs::read_to_string(db.dir.join(".gitignore")).unwrap(), ".local/\n");
+        assert_eq!(fs::read_to_string(db.dir.join(".gitattributes")).unwrap(), "*.json merge=jira-json\n");
+        assert_eq!(db.read_db().unwrap(), db_state);
+    }
+
+    #[test]
+    fn archive_should_use_the_same_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DirectoryDatabase::create(dir.path().join("db"), &DBState::default()).unwrap();
+
+        assert_eq!(db.read_archive().unwrap(), DBState::default());
+
+        db.write_archive(&setup()).unwrap();
+        assert!(db.dir.join("archive/epics/1.json").is_file());
+        assert_eq!(db.read_archive().unwrap(), setup());
+
+        //the archive doesn't leak into the live state
+        assert!(db.read_db().unwrap().epics.is_empty());
+    }
+}
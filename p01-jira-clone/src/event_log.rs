@@ -0,0 +1,496 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::db::Database;
+use crate::models::DBState;
+
+// a snapshot of the replayed state is cached after this many new events, so reads don't have to replay the whole log
+const SNAPSHOT_EVERY: usize = 100;
+
+// one change to the database, stored as a line of JSON in the log
+// changes work on the serialized form of DBState, so new fields on DBState are picked up without new kinds of change
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    // the whole state, which is how a log starts and what compaction leaves behind
+    Snapshot { state: Value },
+    // sets a top level field of DBState, or one entry inside it when there is a key
+    Set { field: String, key: Option<String>, value: Value },
+    Remove { field: String, key: String },
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+// the state after the first `offset` bytes of the log, cached in a file next to it
+#[derive(Serialize, Deserialize)]
+struct CachedSnapshot {
+    offset: u64,
+    state: Value,
+}
+
+// a Database that appends every change to a JSON Lines file instead of rewriting the whole state,
+// and gets the state back by replaying the log
+pub(crate) struct EventLogDatabase {
+    pub log_path: String,
+}
+
+impl EventLogDatabase {
+    // starts a new log holding the given state, refusing to overwrite a log that already exists
+    pub fn create(log_path: String, db_state: &DBState) -> Result<Self> {
+        if Path::new(&log_path).exists() {
+            return Err(anyhow!("{log_path} already exists"));
+        }
+
+        let db = EventLogDatabase { log_path };
+        let mut state = serde_json::to_value(db_state)?;
+        db.write_history(&mut state)?;
+        let event = Event {
+            at: Utc::now(),
+            change: Change::Snapshot { state },
+        };
+        db.append(&[event])?;
+        Ok(db)
+    }
+
+    // data/db.events.jsonl keeps its cached snapshot in data/db.events.snapshot.json
+    // and its undo history in data/db.events.history.json
+    fn sibling_path(&self, suffix: &str) -> String {
+        match self.log_path.strip_suffix(".jsonl") {
+            Some(stem) => format!("{stem}.{suffix}.json"),
+            None => format!("{}.{suffix}.json", self.log_path),
+        }
+    }
+
+    fn read_events(&self) -> Result<Vec<Event>> {
+        let contents = fs::read_to_string(&self.log_path)?;
+        parse_events(&contents)
+    }
+
+    fn append(&self, events: &[Event]) -> Result<()> {
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+
+    // replays the log onto the cached snapshot if there is a usable one, otherwise from the start
+    // also returns how many events had to be replayed
+    fn replay(&self) -> Result<(Value, usize)> {
+        let mut file = fs::File::open(&self.log_path)?;
+        let length = file.metadata()?.len();
+
+        let (mut state, offset) = match self.read_cached_snapshot() {
+            Some(snapshot) if snapshot.offset <= length => (snapshot.state, snapshot.offset),
+            _ => (serde_json::to_value(DBState::default())?, 0),
+        };
+
+        file.seek(SeekFrom::Start(offset))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let events = parse_events(&contents)?;
+        for event in &events {
+            apply_change(&mut state, &event.change);
+        }
+
+        Ok((state, events.len()))
+    }
+
+    // a snapshot that can't be read is ignored, since the log alone is always enough
+    fn read_cached_snapshot(&self) -> Option<CachedSnapshot> {
+        let contents = fs::read_to_string(self.sibling_path("snapshot")).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    // the undo history is taken out of the state and kept in a file of its own, since nearly every change pushes onto it,
+    // and logging the whole stack each time would make every append as big as the history
+    fn write_history(&self, state: &mut Value) -> Result<()> {
+        let Some(history) = state.as_object_mut().and_then(|fields| fields.remove("history")) else {
+            return Ok(());
+        };
+
+        let path = self.sibling_path("history");
+        let contents = serde_json::to_string(&history)?;
+        if fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+            return Ok(());
+        }
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    fn write_cached_snapshot(&self, state: &Value) -> Result<()> {
+        let snapshot = CachedSnapshot {
+            offset: fs::metadata(&self.log_path)?.len(),
+            state: state.clone(),
+        };
+        fs::write(self.sibling_path("snapshot"), serde_json::to_vec(&snapshot)?)?;
+        Ok(())
+    }
+}
+
+impl Database for EventLogDatabase {
+    fn read_db(&self) -> Result<DBState> {
+        let (mut state, _) = self.replay()?;
+
+        //logs written before the history had a file of its own still have it in their events, which is used until the next write
+        let history_path = self.sibling_path("history");
+        if Path::new(&history_path).exists() {
+            state["history"] = serde_json::from_str(&fs::read_to_string(&history_path)?)?;
+        }
+
+        serde_json::from_value(state).context("The event log doesn't replay to a valid database")
+    }
+
+    fn write_db(&self, db_state: &DBState) -> Result<()> {
+        let (old_state, replayed) = self.replay()?;
+        let mut new_state = serde_json::to_value(db_state)?;
+        //a field missing from the new state isn't logged as a change, so what is left of the history in an older log stays put
+        self.write_history(&mut new_state)?;
+
+        let events: Vec<Event> = diff(&old_state, &new_state)
+            .into_iter()
+            .map(|change| Event { at: Utc::now(), change })
+            .collect();
+
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.append(&events)?;
+
+        if replayed + events.len() >= SNAPSHOT_EVERY {
+            self.write_cached_snapshot(&new_state)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_archive(&self) -> Result<DBState> {
+        let path = self.sibling_path("archive");
+
+        if !Path::new(&path).exists() {
+            return Ok(DBState::default());
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn write_archive(&self, archive: &DBState) -> Result<()> {
+        fs::write(self.sibling_path("archive"), serde_json::to_vec(archive)?)?;
+        Ok(())
+    }
+
+    fn compact(&self, before: DateTime<Utc>) -> Result<usize> {
+        let events = self.read_events()?;
+        let folded = events.iter().take_while(|event| event.at < before).count();
+
+        //a single event is already as compact as it gets
+        if folded < 2 {
+            return Ok(0);
+        }
+
+        let mut state = serde_json::to_value(DBState::default())?;
+        for event in &events[..folded] {
+            apply_change(&mut state, &event.change);
+        }
+
+        let snapshot = Event {
+            at: events[folded - 1].at,
+            change: Change::Snapshot { state },
+        };
+
+        let mut contents = String::new();
+        for event in std::iter::once(&snapshot).chain(&events[folded..]) {
+            contents.push_str(&serde_json::to_string(event)?);
+            contents.push('\n');
+        }
+
+        //the new log is written alongside and then moved over the old one, so a failure can't leave half a log behind
+        let compacted_path = format!("{}.compacting", self.log_path);
+        fs::write(&compacted_path, contents)?;
+        fs::rename(&compacted_path, &self.log_path)?;
+
+        //the cached snapshot points at an offset in the old log
+        let _ = fs::remove_file(self.sibling_path("snapshot"));
+
+        Ok(folded)
+    }
+
+    fn read_db_at(&self, at: DateTime<Utc>) -> Result<DBState> {
+        let events = self.read_events()?;
+
+        //compaction throws away the detail from before the snapshot it leaves, so the log can't go back any further than that
+        match events.first() {
+            Some(first) if first.at <= at => {}
+            Some(first) => {
+                return Err(anyhow!(
+                    "The event log only goes back to {}",
+                    first.at.format("%Y-%m-%d %H:%M:%S")
+                ))
+            }
+            None => return Err(anyhow!("The event log is empty")),
+        }
+
+        let mut state = serde_json::to_value(DBState::default())?;
+        for event in events.iter().take_while(|event| event.at <= at) {
+            apply_change(&mut state, &event.change);
+        }
+
+        serde_json::from_value(state).context("The event log doesn't replay to a valid database")
+    }
+}
+
+fn parse_events(contents: &str) -> Result<Vec<Event>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Invalid event on line {} of the log", i + 1)))
+        .collect()
+}
+
+pub(crate) fn apply_change(state: &mut Value, change: &Change) {
+    match change {
+        Change::Snapshot { state: snapshot } => *state = snapshot.clone(),
+        Change::Set { field, key: None, value } => {
+            state[field.as_str()] = value.clone();
+        }
+        Change::Set { field, key: Some(key), value } => {
+            if !state[field.as_str()].is_object() {
+                state[field.as_str()] = Value::Object(Map::new());
+            }
+            state[field.as_str()][key.as_str()] = value.clone();
+        }
+        Change::Remove { field, key } => {
+            if let Some(entries) = state[field.as_str()].as_object_mut() {
+                entries.remove(key);
+            }
+        }
+    }
+}
+
+// the changes that turn old into new
+// fields that are objects, like epics and stories, are compared entry by entry so that changing one story only logs that story
+pub(crate) fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = vec![];
+
+    let Some(new_fields) = new.as_object() else {
+        return vec![Change::Snapshot { state: new.clone() }];
+    };
+
+    for (field, new_value) in new_fields {
+        let old_value = old.get(field);
+        if old_value == Some(new_value) {
+            continue;
+        }
+
+        match (old_value.and_then(Value::as_object), new_value.as_object()) {
+            (Some(old_entries), Some(new_entries)) => {
+                for (key, value) in new_entries {
+                    if old_entries.get(key) != Some(value) {
+                        changes.push(Change::Set {
+                            field: field.clone(),
+                            key: Some(key.clone()),
+                            value: value.clone(),
+                        });
+                    }
+                }
+                for key in old_entries.keys().filter(|key| !new_entries.contains_key(*key)) {
+                    changes.push(Change::Remove {
+                        field: field.clone(),
+                        key: key.clone(),
+                    });
+                }
+            }
+            _ => changes.push(Change::Set {
+                field: field.clone(),
+                key: None,
+                value: new_value.clone(),
+            }),
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DbIndex, Epic, Operation, Status, Story};
+    use chrono::Duration;
+
+    fn new_log(dir: &tempfile::TempDir) -> EventLogDatabase {
+        let log_path = dir.path().join("db.events.jsonl").to_str().unwrap().to_owned();
+        EventLogDatabase::create(log_path, &DBState::default()).unwrap()
+    }
+
+    fn line_count(db: &EventLogDatabase) -> usize {
+        fs::read_to_string(&db.log_path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn create_should_error_if_log_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_log(&dir);
+
+        assert!(EventLogDatabase::create(db.log_path.clone(), &DBState::default()).is_err());
+    }
+
+    #[test]
+    fn diff_should_only_include_changed_entries() {
+        let mut old = DBState::default();
+        old.epics.insert(1, Epic::new("".to_owned(), "".to_owned()));
+        old.stories.insert(2, Story::new("".to_owned(), "".to_owned()));
+        old.stories.insert(3, Story::new("".to_owned(), "".to_owned()));
+
+        let mut new = old.clone();
+        new.stories.get_mut(&2).unwrap().status = Status::Closed;
+        new.stories.remove(&3);
+
+        let changes = diff(&serde_json::to_value(&old).unwrap(), &serde_json::to_value(&new).unwrap());
+
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(&changes[0], Change::Set { field, key: Some(key), .. } if field == "stories" && key == "2"));
+        assert!(matches!(&changes[1], Change::Remove { field, key } if field == "stories" && key == "3"));
+    }
+
+    #[test]
+    fn write_db_should_append_events_that_replay_to_the_same_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_log(&dir);
+
+        let mut state = DBState {
+            last_item_id: 1,
+            ..Default::default()
+        };
+        state.epics.insert(1, Epic::new("Accounts".to_owned(), "".to_owned()));
+        db.write_db(&state).unwrap();
+
+        //the snapshot, then one event for last_item_id and one for the new epic
+        assert_eq!(line_count(&db), 3);
+        assert_eq!(db.read_db().unwrap(), state);
+
+        //writing the same state again has nothing to log
+        db.write_db(&state).unwrap();
+        assert_eq!(line_count(&db), 3);
+    }
+
+    #[test]
+    fn write_db_should_keep_the_history_out_of_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_log(&dir);
+
+        let mut state = DBState {
+            last_item_id: 1,
+            ..Default::default()
+        };
+        state.epics.insert(1, Epic::new("Accounts".to_owned(), "".to_owned()));
+        state.history.record(Operation::RemoveEpic { epic_id: 1 });
+        db.write_db(&state).unwrap();
+
+        assert_eq!(line_count(&db), 3);
+        assert!(!fs::read_to_string(&db.log_path).unwrap().contains("RemoveEpic"));
+        assert_eq!(db.read_db().unwrap(), state);
+
+        //a log from before the history had its own file keeps what it logged until the next write
+        fs::remove_file(db.sibling_path("history")).unwrap();
+        let history = serde_json::to_value(&state.history).unwrap();
+        let change = Change::Set {
+            field: "history".to_owned(),
+            key: None,
+            value: history,
+        };
+        db.append(&[Event { at: Utc::now(), change }]).unwrap();
+        assert_eq!(db.read_db().unwrap(), state);
+
+        state.history.record(Operation::RemoveEpic { epic_id: 2 });
+        db.write_db(&state).unwrap();
+        assert_eq!(line_count(&db), 4);
+        assert_eq!(db.read_db().unwrap(), state);
+    }
+
+    #[test]
+    fn read_db_should_use_the_cached_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_log(&dir);
+
+        let mut state = DBState::default();
+        for id in 1..=SNAPSHOT_EVERY as DbIndex {
+            state.last_item_id = id;
+            db.write_db(&state).unwrap();
+        }
+
+        //only the events written after the snapshot are replayed
+        let snapshot = db.read_cached_snapshot().unwrap();
+        assert!(snapshot.offset > 0);
+        assert_eq!(db.replay().unwrap().1, 1);
+        assert_eq!(db.read_db().unwrap(), state);
+    }
+
+    #[test]
+    fn read_db_should_error_on_a_corrupt_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_log(&dir);
+        fs::write(&db.log_path, "not an event\n").unwrap();
+
+        assert!(db.read_db().is_err());
+    }
+
+    #[test]
+    fn read_db_at_should_replay_up_to_the_given_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let before_log = Utc::now() - Duration::seconds(1);
+        let db = new_log(&dir);
+
+        let mut state = DBState::default();
+        state.epics.insert(1, Epic::new("".to_owned(), "".to_owned()));
+        db.write_db(&state).unwrap();
+
+        let friday = Utc::now();
+
+        state.epics.get_mut(&1).unwrap().status = Status::Closed;
+        db.write_db(&state).unwrap();
+
+        assert_eq!(db.read_db_at(friday).unwrap().epics[&1].status, Status::Open);
+        assert_eq!(db.read_db_at(Utc::now()).unwrap().epics[&1].status, Status::Closed);
+        assert!(db.read_db_at(before_log).is_err());
+    }
+
+    #[test]
+    fn compact_should_fold_old_events_into_a_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_log(&dir);
+
+        let mut state = DBState::default();
+        for id in 1..=3 {
+            state.last_item_id = id;
+            db.write_db(&state).unwrap();
+        }
+
+        assert_eq!(db.compact(Utc::now() + Duration::seconds(1)).unwrap(), 4);
+        assert_eq!(line_count(&db), 1);
+        assert_eq!(db.read_db().unwrap(), state);
+
+        //there's nothing left to fold
+        assert_eq!(db.compact(Utc::now() + Duration::seconds(1)).unwrap(), 0);
+    }
+}
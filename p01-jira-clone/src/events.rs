@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::models::{DBState, DbIndex};
+
+// events describe a change to one epic or story, for webhooks and hook scripts:
+//   {"event": "story.status_changed", "at": ..., "item": {"kind", "id", "key"}, "old": ..., "new": ...}
+// <kind>.created and <kind>.deleted or <kind>.archived have the whole item as new or old
+
+// the fields of epics and stories that get a <kind>.<field>_changed event when they change
+const EPIC_FIELDS: [&str; 3] = ["name", "description", "status"];
+const STORY_FIELDS: [&str; 6] = ["name", "description", "status", "assignee", "estimate", "commits"];
+
+// the events for everything that changed between two states of the database
+pub(crate) fn changes(before: &DBState, after: &DBState, now: DateTime<Utc>) -> Vec<Value> {
+    let mut events = vec![];
+
+    for (kind, fields) in [("epic", &EPIC_FIELDS[..]), ("story", &STORY_FIELDS[..])] {
+        let before_items = items(before, kind);
+        let after_items = items(after, kind);
+        let ids: BTreeSet<&DbIndex> = before_items.keys().chain(after_items.keys()).collect();
+
+        for id in ids {
+            let event = |event_type: String, state: &DBState, old: Value, new: Value| {
+                json!({
+                    "event": event_type,
+                    "at": now,
+                    "item": { "kind": kind, "id": id, "key": state.display_key(*id) },
+                    "old": old,
+                    "new": new,
+                })
+            };
+
+            match (before_items.get(id), after_items.get(id)) {
+                (None, Some(item)) => events.push(event(format!("{kind}.created"), after, Value::Null, item.clone())),
+                (Some(item), None) => {
+                    //archiving takes items out of the live state too, but they still exist
+                    let removal = if after.archived_ids.contains(id) { "archived" } else { "deleted" };
+                    events.push(event(format!("{kind}.{removal}"), before, item.clone(), Value::Null));
+                }
+                (Some(old), Some(new)) => {
+                    for field in fields {
+                        if old[field] != new[field] {
+                            let event_type = format!("{kind}.{field}_changed");
+                            events.push(event(event_type, after, old[field].clone(), new[field].clone()));
+                        }
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    events
+}
+
+// the epics or stories of a state as json, so that their fields can be compared by name
+fn items(db_state: &DBState, kind: &str) -> BTreeMap<DbIndex, Value> {
+    match kind {
+        "epic" => db_state.epics.iter().map(|(id, epic)| (*id, to_value(epic))).collect(),
+        _ => db_state.stories.iter().map(|(id, story)| (*id, to_value(story))).collect(),
+    }
+}
+
+fn to_value<T: Serialize>(item: &T) -> Value {
+    serde_json::to_value(item).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Epic;
+
+    #[test]
+    fn changes_should_tell_archiving_from_deleting() {
+        let mut before = DBState::default();
+        before.epics.insert(1, Epic::new("".to_owned(), "".to_owned()));
+        before.epics.insert(2, Epic::new("".to_owned(), "".to_owned()));
+
+        let mut after = before.clone();
+        after.epics.clear();
+        after.archived_ids.insert(2);
+
+        let events = changes(&before, &after, Utc::now());
+        let types: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["epic.deleted", "epic.archived"]);
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+
+use crate::models::{DBState, DbIndex, Status, Story};
+
+const CSV_HEADERS: [&str; 9] = ["Epic key", "Epic", "Epic status", "Story key", "Story", "Status", "Assignee", "Estimate", "Description"];
+
+// every story, epic by epic in the order the epics were created, for exports that aren't filtered
+pub fn all_stories(db_state: &DBState) -> Vec<(DbIndex, Story)> {
+    let mut epic_ids: Vec<&DbIndex> = db_state.epics.keys().collect();
+    epic_ids.sort();
+
+    epic_ids
+        .into_iter()
+        .flat_map(|epic_id| &db_state.epics[epic_id].stories)
+        .filter_map(|story_id| db_state.stories.get(story_id).map(|story| (*story_id, story.clone())))
+        .collect()
+}
+
+// the stories of one epic, or of no epic, that are being exported
+type EpicGroup<'a> = (Option<DbIndex>, Vec<&'a (DbIndex, Story)>);
+
+fn epic_of(db_state: &DBState, story_id: DbIndex) -> Option<DbIndex> {
+    db_state.epics.iter().find(|(_, epic)| epic.stories.contains(&story_id)).map(|(epic_id, _)| *epic_id)
+}
+
+// one row per story with its epic in the first columns, in the order the stories are given
+pub fn export_csv(db_state: &DBState, stories: &[(DbIndex, Story)]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(CSV_HEADERS)?;
+
+    for (story_id, story) in stories {
+        let epic_id = epic_of(db_state, *story_id);
+        let epic = epic_id.map(|epic_id| &db_state.epics[&epic_id]);
+
+        writer.write_record([
+            epic_id.map(|epic_id| db_state.display_key(epic_id)).unwrap_or_default(),
+            epic.map(|epic| epic.name.clone()).unwrap_or_default(),
+            epic.map(|epic| epic.status.to_string()).unwrap_or_default(),
+            db_state.display_key(*story_id),
+            story.name.clone(),
+            story.status.to_string(),
+            story.assignee.clone().unwrap_or_default(),
+            story.estimate.map(|points| points.to_string()).unwrap_or_default(),
+            story.description.clone(),
+        ])?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+// a heading for each epic with its stories as a task list under it, resolved and closed stories are ticked off
+// epics come in the order their first story is given, so a sorted filter keeps its order within each epic
+pub fn export_markdown(db_state: &DBState, stories: &[(DbIndex, Story)], title: &str) -> String {
+    let mut groups: Vec<EpicGroup> = vec![];
+    for entry in stories {
+        let epic_id = epic_of(db_state, entry.0);
+        match groups.iter_mut().find(|(group_epic_id, _)| *group_epic_id == epic_id) {
+            Some((_, group)) => group.push(entry),
+            None => groups.push((epic_id, vec![entry])),
+        }
+    }
+
+    let mut markdown = format!("# {title}\n");
+    if groups.is_empty() {
+        markdown.push_str("\nNo stories.\n");
+    }
+
+    for (epic_id, group) in groups {
+        match epic_id {
+            Some(epic_id) => {
+                let epic = &db_state.epics[&epic_id];
+                markdown.push_str(&format!("\n## {} {} ({})\n\n", db_state.display_key(epic_id), one_line(&epic.name), epic.status));
+                if !epic.description.trim().is_empty() {
+                    markdown.push_str(&format!("{}\n\n", epic.description.trim()));
+                }
+            }
+            None => markdown.push_str("\n## No epic\n\n"),
+        }
+
+        for (story_id, story) in group {
+            let done = if matches!(story.status, Status::Resolved | Status::Closed) { "x" } else { " " };
+            let mut details = vec![story.status.to_string()];
+            details.extend(story.assignee.as_ref().map(|assignee| format!("@{assignee}")));
+            details.extend(story.estimate.map(|points| format!("{points} points")));
+
+            markdown.push_str(&format!(
+                "- [{done}] {} {} ({})\n",
+                db_state.display_key(*story_id),
+                one_line(&story.name),
+                details.join(", ")
+            ));
+            //the description is indented so that it stays part of the list item
+            for line in story.description.trim().lines() {
+                match line.trim().is_empty() {
+                    true => markdown.push('\n'),
+                    false => markdown.push_str(&format!("  {line}\n")),
+                }
+            }
+        }
+    }
+
+    markdown
+}
+
+// names are typed on one line in the ui, but imported ones might not be
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Epic;
+
+    fn setup() -> DBState {
+        let mut db_state = DBState::default();
+        let mut epic = Epic::new("Accounts".to_owned(), "Logging in and out".to_owned());
+        epic.stories = vec![2, 3];
+        db_state.epics.insert(1, epic);
+
+        let mut login = Story::new("Login, with \"remember me\"".to_owned(), "A checkbox\nunder the password".to_owned());
+        login.assignee = Some("sam".to_owned());
+        login.estimate = Some(3);
+        db_state.stories.insert(2, login);
+
+        let mut logout = Story::new("Logout".to_owned(), "".to_owned());
+        logout.status = Status::Closed;
+        db_state.stories.insert(3, logout);
+        db_state.last_item_id = 3;
+        db_state
+    }
+
+    #[test]
+    fn export_csv_should_write_a_row_per_story_with_its_epic() {
+        let db_state = setup();
+
+        let csv = export_csv(&db_state, &all_stories(&db_state)).unwrap();
+
+        assert_eq!(
+            csv,
+            "Epic key,Epic,Epic status,Story key,Story,Status,Assignee,Estimate,Description\n\
+             1,Accounts,OPEN,2,\"Login, with \"\"remember me\"\"\",OPEN,sam,3,\"A checkbox\nunder the password\"\n\
+             1,Accounts,OPEN,3,Logout,CLOSED,,,\n"
+        );
+    }
+
+    #[test]
+    fn export_markdown_should_list_stories_as_tasks_under_their_epic() {
+        let db_state = setup();
+
+        let markdown = export_markdown(&db_state, &all_stories(&db_state), "Backlog");
+
+        assert_eq!(
+            markdown,
+            "# Backlog\n\n\
+             ## 1 Accounts (OPEN)\n\n\
+             Logging in and out\n\n\
+             - [ ] 2 Login, with \"remember me\" (OPEN, @sam, 3 points)\n  A checkbox\n  under the password\n\
+             - [x] 3 Logout (CLOSED)\n"
+        );
+        assert_eq!(export_markdown(&db_state, &[], "Nothing"), "# Nothing\n\nNo stories.\n");
+    }
+}
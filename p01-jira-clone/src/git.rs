@@ -0,0 +1,335 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::db::JiraDatabase;
+use crate::models::Status;
+
+// the words in a commit message that come before the stories it is about, like "Fixes #12" or "Refs WEB-4, WEB-5"
+// a commit that fixes a story can resolve it, one that only refers to it just gets linked
+const FIXING_WORDS: [&str; 9] = ["fix", "fixes", "fixed", "close", "closes", "closed", "resolve", "resolves", "resolved"];
+const REFERRING_WORDS: [&str; 4] = ["ref", "refs", "references", "see"];
+
+// the line under the #! of the hooks that `git install-hooks` writes, so that they can be replaced but nobody else's hooks are
+const HOOK_MARKER: &str = "# installed by jira-clone";
+
+// a story key in a commit message, and whether the commit says it fixes the story
+#[derive(PartialEq, Debug, Clone)]
+pub struct Mention {
+    pub key: String,
+    pub fixes: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Commit {
+    pub hash: String,
+    pub message: String,
+}
+
+// finds the story keys in a commit message, each key only once
+// a plain number only counts as a key after a colon, like "Refs: 12", or when the keys run to the end of the line,
+// like "Refs 12", so that "Fix 2 typos" isn't taken to be about story 2
+pub fn parse_message(message: &str) -> Vec<Mention> {
+    let mut mentions: Vec<Mention> = vec![];
+
+    for line in message.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let mut i = 0;
+        while i < words.len() {
+            let after_colon = words[i].ends_with(':');
+            let word = words[i].trim_end_matches(':').to_lowercase();
+            i += 1;
+
+            let fixes = FIXING_WORDS.contains(&word.as_str());
+            if !fixes && !REFERRING_WORDS.contains(&word.as_str()) {
+                continue;
+            }
+
+            //a list of keys can follow, like "Fixes #12, #13 and #14"
+            let mut keys = vec![];
+            while let Some(word) = words.get(i) {
+                if word.eq_ignore_ascii_case("and") || *word == "&" {
+                    i += 1;
+                    continue;
+                }
+                let Some(key) = as_key(word) else {
+                    break;
+                };
+                i += 1;
+                keys.push(key);
+            }
+
+            let plain_numbers_count = after_colon || i == words.len();
+            for (key, plain_number) in keys {
+                if plain_number && !plain_numbers_count {
+                    continue;
+                }
+
+                match mentions.iter_mut().find(|mention| mention.key.eq_ignore_ascii_case(&key)) {
+                    Some(mention) => mention.fixes |= fixes,
+                    None => mentions.push(Mention { key, fixes }),
+                }
+            }
+        }
+    }
+
+    mentions
+}
+
+// a word that looks like a key, a number or PREFIX-number with an optional # in front and punctuation after
+// also returns whether it is a plain number, without the # or the prefix
+fn as_key(word: &str) -> Option<(String, bool)> {
+    let word = word.trim_start_matches(['(', '[']);
+    let key = word.trim_start_matches('#');
+    let key = key.trim_end_matches([',', '.', ';', ':', ')', ']', '!']);
+
+    let (number, plain_number) = match key.rsplit_once('-') {
+        Some((prefix, number)) if !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_alphanumeric()) => (number, false),
+        Some(_) => return None,
+        None => (key, !word.starts_with('#')),
+    };
+    match !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+        true => Some((key.to_owned(), plain_number)),
+        false => None,
+    }
+}
+
+// links the commit to the stories its message mentions, and resolves the ones it fixes when resolve is set
+// returns what was done, one line for each change
+pub fn apply_commit(db: &JiraDatabase, commit: &Commit, resolve: bool) -> Result<Vec<String>> {
+    let mut done = vec![];
+
+    for mention in parse_message(&commit.message) {
+        let db_state = db.read_db()?;
+        let Some(story_id) = db_state.resolve_key(&mention.key).filter(|id| db_state.stories.contains_key(id)) else {
+            continue;
+        };
+        let key = db_state.display_key(story_id);
+
+        //a commit that is already linked has been seen before, so its story isn't resolved again after being reopened
+        if !db.link_commit(story_id, &commit.hash)? {
+            continue;
+        }
+        done.push(format!("Linked {} to {key}", short_hash(&commit.hash)));
+
+        let status = &db_state.stories[&story_id].status;
+        if resolve && mention.fixes && matches!(status, Status::Open | Status::InProgress) {
+            db.update_story_status(story_id, Status::Resolved)?;
+            done.push(format!("Resolved {key}"));
+        }
+    }
+
+    Ok(done)
+}
+
+// every key in a commit message has to be a story, so that typos are caught before the commit is made
+pub fn check_message(db: &JiraDatabase, message: &str) -> Result<()> {
+    let db_state = db.read_db()?;
+
+    //git leaves out the lines starting with # when it saves the message, so they don't count
+    let message: Vec<&str> = message.lines().filter(|line| !line.starts_with('#')).collect();
+
+    for mention in parse_message(&message.join("\n")) {
+        if !db_state.resolve_key(&mention.key).is_some_and(|id| db_state.stories.contains_key(&id)) {
+            return Err(anyhow!("The commit message mentions {}, which isn't a story", mention.key));
+        }
+    }
+    Ok(())
+}
+
+fn short_hash(hash: &str) -> &str {
+    &hash[..hash.len().min(7)]
+}
+
+// runs git in the given repository and returns what it printed
+fn git(repo: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .context("Couldn't run git, is it installed?")?;
+
+    if !output.status.success() {
+        return Err(anyhow!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// every commit in the history of the checked out branch, oldest first
+pub fn read_history(repo: &Path) -> Result<Vec<Commit>> {
+    //the fields of a commit are separated by a unit separator and commits by a record separator, neither of which is in messages
+    let log = git(repo, &["log", "--reverse", "--format=%H%x1f%B%x1e"])?;
+
+    Ok(log
+        .split('\x1e')
+        .filter_map(|record| record.trim_start().split_once('\x1f'))
+        .map(|(hash, message)| Commit {
+            hash: hash.to_owned(),
+            message: message.trim().to_owned(),
+        })
+        .collect())
+}
+
+// the commit that was just made
+pub fn read_head(repo: &Path) -> Result<Commit> {
+    let log = git(repo, &["log", "-1", "--format=%H%x1f%B"])?;
+    let (hash, message) = log.split_once('\x1f').ok_or_else(|| anyhow!("git log didn't print a commit"))?;
+
+    Ok(Commit {
+        hash: hash.trim().to_owned(),
+        message: message.trim().to_owned(),
+    })
+}
+
+// writes commit-msg and post-commit hooks that run the given program, returning where they were written
+pub fn install_hooks(repo: &Path, program: &Path, resolve: bool) -> Result<Vec<PathBuf>> {
+    //git says where the hooks go, which isn't always .git/hooks, like in a worktree or with core.hooksPath set
+    let hooks_dir = repo.join(git(repo, &["rev-parse", "--git-path", "hooks"])?.trim());
+    fs::create_dir_all(&hooks_dir)?;
+
+    //single quotes keep spaces in the path together, and a quote in the path is closed, escaped and opened again
+    let program = format!("'{}'", program.to_string_lossy().replace('\'', r"'\''"));
+    let post_commit = match resolve {
+        true => format!("exec {program} git post-commit --resolve"),
+        false => format!("exec {program} git post-commit"),
+    };
+
+    let mut written = vec![];
+    for (name, command) in [("commit-msg", format!("exec {program} git check-message \"$1\"")), ("post-commit", post_commit)] {
+        let path = hooks_dir.join(name);
+        if let Ok(existing) = fs::read_to_string(&path) {
+            if existing.lines().nth(1) != Some(HOOK_MARKER) {
+                return Err(anyhow!("{} already exists and wasn't installed by jira-clone, nothing was changed", path.display()));
+            }
+        }
+        written.push((path, format!("#!/bin/sh\n{HOOK_MARKER}\n{command}\n")));
+    }
+
+    for (path, contents) in &written {
+        fs::write(path, contents)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+
+    Ok(written.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use crate::models::{Epic, Story};
+
+    fn mention(key: &str, fixes: bool) -> Mention {
+        Mention { key: key.to_owned(), fixes }
+    }
+
+    fn commit(hash: &str, message: &str) -> Commit {
+        Commit {
+            hash: hash.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_message_should_find_fixed_and_referenced_keys() {
+        assert_eq!(parse_message("Fixes #12"), vec![mention("12", true)]);
+        assert_eq!(parse_message("Add login page\n\nRefs 12"), vec![mention("12", false)]);
+        assert_eq!(
+            parse_message("closes: WEB-4, WEB-5 and #6. See (WEB-7)"),
+            vec![mention("WEB-4", true), mention("WEB-5", true), mention("6", true), mention("WEB-7", false)]
+        );
+        assert_eq!(parse_message("Refs 3, fixes 3"), vec![mention("3", true)]);
+        assert_eq!(parse_message("Fix the login page"), vec![]);
+        assert_eq!(parse_message("Issue 12 is fixed by this"), vec![]);
+        assert_eq!(parse_message("Refs: 12 and 13 from the design review"), vec![mention("12", false), mention("13", false)]);
+    }
+
+    #[test]
+    fn parse_message_should_not_take_counts_for_keys() {
+        assert_eq!(parse_message("Fix 2 typos"), vec![]);
+        assert_eq!(parse_message("Add login page
+
+see 3 examples in the docs"), vec![]);
+        assert_eq!(parse_message("Closed 4 tabs, fixes #5"), vec![mention("5", true)]);
+        assert_eq!(parse_message("Fix 2 typos in WEB-3
+Refs 4"), vec![mention("4", false)]);
+    }
+
+    #[test]
+    fn apply_commit_should_link_once_and_resolve_fixed_stories() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        let fixed = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+        let referenced = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+
+        let done = apply_commit(&db, &commit("0123456789abcdef", "Fixes #2, refs #3 and #1"), true).unwrap();
+
+        assert_eq!(done, vec!["Linked 0123456 to 2", "Resolved 2", "Linked 0123456 to 3"]);
+        let db_state = db.read_db().unwrap();
+        assert_eq!(db_state.stories[&fixed].status, Status::Resolved);
+        assert_eq!(db_state.stories[&fixed].commits, vec!["0123456789abcdef".to_owned()]);
+        assert_eq!(db_state.stories[&referenced].status, Status::Open);
+
+        //reopened after the fix, seeing the same commit again shouldn't resolve it again
+        db.update_story_status(fixed, Status::Open).unwrap();
+        assert!(apply_commit(&db, &commit("0123456789abcdef", "Fixes #2"), true).unwrap().is_empty());
+        assert_eq!(db.read_db().unwrap().stories[&fixed].status, Status::Open);
+
+        //without resolve the commit is only linked
+        apply_commit(&db, &commit("fedcba", "Fixes #2"), false).unwrap();
+        assert_eq!(db.read_db().unwrap().stories[&fixed].status, Status::Open);
+        assert_eq!(db.read_db().unwrap().stories[&fixed].commits.len(), 2);
+    }
+
+    #[test]
+    fn check_message_should_reject_keys_that_are_not_stories() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+
+        assert!(check_message(&db, "Fixes #2\n# Refs #9 is a comment").is_ok());
+        assert!(check_message(&db, "No stories here").is_ok());
+        assert!(check_message(&db, "Fix 9 typos").is_ok());
+        let error = check_message(&db, "Fixes #1").unwrap_err();
+        assert_eq!(error.to_string(), "The commit message mentions 1, which isn't a story");
+    }
+
+    #[test]
+    fn read_history_and_install_hooks_should_work_on_a_real_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        for args in [
+            vec!["init", "-q"],
+            vec!["-c", "user.name=a", "-c", "user.email=a@b", "commit", "-q", "--allow-empty", "-m", "Start"],
+            vec!["-c", "user.name=a", "-c", "user.email=a@b", "commit", "-q", "--allow-empty", "-m", "Login\n\nFixes #2"],
+        ] {
+            git(repo, &args).unwrap();
+        }
+
+        let history = read_history(repo).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].message, "Login\n\nFixes #2");
+        assert_eq!(read_head(repo).unwrap(), history[1]);
+
+        let hooks = install_hooks(repo, Path::new("/opt/it's here/jira"), true).unwrap();
+        let post_commit = fs::read_to_string(&hooks[1]).unwrap();
+        assert_eq!(post_commit, "#!/bin/sh\n# installed by jira-clone\nexec '/opt/it'\\''s here/jira' git post-commit --resolve\n");
+
+        //installing again replaces our own hooks, but not somebody else's
+        assert!(install_hooks(repo, Path::new("jira"), false).is_ok());
+        fs::write(&hooks[0], "#!/bin/sh\nexit 0\n").unwrap();
+        assert!(install_hooks(repo, Path::new("jira"), false).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::Database;
+use crate::events::changes;
+use crate::models::{Backup, DBState};
+use crate::rules::Rule;
+use crate::webhooks::Delivery;
+
+// a program that is run with an event as json on its stdin, set up in the config file
+// the event type is also in the JIRA_EVENT environment variable, for scripts that only want to check that
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Hook {
+    // an event type like story.status_changed, or * for every event
+    pub event: String,
+    pub stage: Stage,
+    // a path with a / in it is relative to the config file, anything else is looked up on the PATH
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    // runs before the change is saved, and stops it from being saved by exiting with an error
+    Pre,
+    // runs after the change has been saved
+    Post,
+}
+
+// a pre hook saying no, which is shown as the hook's own message
+#[derive(Debug)]
+pub struct HookVeto(pub String);
+
+impl std::fmt::Display for HookVeto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for HookVeto {}
+
+impl Hook {
+    fn wants(&self, stage: Stage, event_type: &str) -> bool {
+        self.stage == stage && (self.event == "*" || self.event == event_type)
+    }
+
+    // runs the hook with the event on stdin, returning an error with the hook's stderr if it exits with an error
+    fn run(&self, event: &Value) -> Result<()> {
+        let event_type = event["event"].as_str().unwrap_or_default();
+
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .env("JIRA_EVENT", event_type)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Couldn't run the {event_type} hook {}", self.command))?;
+
+        //a hook that exits without reading its stdin closes the pipe, which isn't an error for the hook
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(event.to_string().as_bytes());
+        }
+
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        if stderr.is_empty() {
+            let message = format!("The {event_type} hook {} exited with {}", self.command, output.status);
+            return Err(HookVeto(message).into());
+        }
+        Err(HookVeto(stderr).into())
+    }
+}
+
+// a Database that runs the hooks for whatever each write changes
+pub(crate) struct HookDatabase {
+    pub inner: Box<dyn Database>,
+    pub hooks: Vec<Hook>,
+}
+
+impl HookDatabase {
+    fn run_hooks(&self, stage: Stage, events: &[Value]) -> Result<()> {
+        for event in events {
+            let event_type = event["event"].as_str().unwrap_or_default();
+            for hook in self.hooks.iter().filter(|hook| hook.wants(stage, event_type)) {
+                hook.run(event)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Database for HookDatabase {
+    fn read_db(&self) -> Result<DBState> {
+        self.inner.read_db()
+    }
+
+    fn write_db(&self, db_state: &DBState) -> Result<()> {
+        let events = match self.inner.read_db() {
+            Ok(before) => changes(&before, db_state, Utc::now()),
+            Err(_) => vec![],
+        };
+
+        //the first pre hook to fail stops the write, and its stderr becomes the error of whatever was being done
+        self.run_hooks(Stage::Pre, &events)?;
+        self.inner.write_db(db_state)?;
+
+        //a post hook exiting with an error can't take the change back, so it only matters if it couldn't be run at all
+        for event in &events {
+            let event_type = event["event"].as_str().unwrap_or_default();
+            for hook in self.hooks.iter().filter(|hook| hook.wants(Stage::Post, event_type)) {
+                if let Err(error) = hook.run(event) {
+                    if !error.is::<HookVeto>() {
+                        return Err(error.context("The change was saved, but a post hook couldn't be run"));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_archive(&self) -> Result<DBState> {
+        self.inner.read_archive()
+    }
+
+    fn write_archive(&self, archive: &DBState) -> Result<()> {
+        self.inner.write_archive(archive)
+    }
+
+    fn list_backups(&self) -> Result<Vec<Backup>> {
+        self.inner.list_backups()
+    }
+
+    fn restore_backup(&self, id: &str) -> Result<()> {
+        self.inner.restore_backup(id)
+    }
+
+    fn compact(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.inner.compact(before)
+    }
+
+    fn read_db_at(&self, at: DateTime<Utc>) -> Result<DBState> {
+        self.inner.read_db_at(at)
+    }
+
+    fn pending_deliveries(&self) -> Result<Vec<Delivery>> {
+        self.inner.pending_deliveries()
+    }
+
+    fn retry_deliveries(&self) -> Result<usize> {
+        self.inner.retry_deliveries()
+    }
+
+    fn rules(&self) -> Vec<Rule> {
+        self.inner.rules()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use crate::db::JiraDatabase;
+    use crate::models::{Epic, Status, Story};
+    use std::fs;
+
+    // a hook that runs a line of shell
+    fn shell_hook(event: &str, stage: Stage, script: &str) -> Hook {
+        Hook {
+            event: event.to_owned(),
+            stage,
+            command: "sh".to_owned(),
+            args: vec!["-c".to_owned(), script.to_owned()],
+        }
+    }
+
+    fn setup(hooks: Vec<Hook>) -> JiraDatabase {
+        JiraDatabase {
+            database: Box::new(MockDB::new()),
+        }
+        .with_hooks(hooks)
+    }
+
+    #[test]
+    fn pre_hook_should_veto_with_its_stderr() {
+        let db = setup(vec![shell_hook(
+            "story.status_changed",
+            Stage::Pre,
+            "echo 'Stories need an estimate before they are started' >&2; exit 1",
+        )]);
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        let story_id = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+
+        let error = db.update_story_status(story_id, Status::InProgress).unwrap_err();
+
+        assert_eq!(error.to_string(), "Stories need an estimate before they are started");
+        assert_eq!(db.read_db().unwrap().stories[&story_id].status, Status::Open);
+    }
+
+    #[test]
+    fn pre_hook_vetoing_an_undone_archive_should_leave_the_epic_archived() {
+        let dir = tempfile::tempdir().unwrap();
+        let veto = dir.path().join("veto");
+        let db = setup(vec![shell_hook("epic.created", Stage::Pre, &format!("test ! -e '{}'", veto.display()))]);
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        db.update_epic_status(epic_id, Status::Closed).unwrap();
+        db.archive_epic(epic_id).unwrap();
+
+        fs::write(&veto, "").unwrap();
+        assert!(db.undo().is_err());
+
+        assert!(db.read_archive().unwrap().epics.contains_key(&epic_id));
+        assert!(!db.read_db().unwrap().epics.contains_key(&epic_id));
+
+        //and once the hook allows it, the undo is still there to try again
+        fs::remove_file(&veto).unwrap();
+        db.undo().unwrap();
+        assert!(db.read_db().unwrap().epics.contains_key(&epic_id));
+        assert!(db.read_archive().unwrap().epics.is_empty());
+    }
+
+    #[test]
+    fn pre_hook_without_stderr_should_still_explain_the_veto() {
+        let db = setup(vec![shell_hook("*", Stage::Pre, "exit 3")]);
+
+        let error = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap_err();
+
+        assert!(error.to_string().starts_with("The epic.created hook sh exited with"));
+        assert!(db.read_db().unwrap().epics.is_empty());
+    }
+
+    #[test]
+    fn hooks_should_get_the_event_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let pre = dir.path().join("pre.json");
+        let post = dir.path().join("post.txt");
+        let db = setup(vec![
+            shell_hook("story.created", Stage::Pre, &format!("cat > '{}'", pre.display())),
+            shell_hook("*", Stage::Post, &format!("echo $JIRA_EVENT >> '{}'", post.display())),
+        ]);
+
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        db.create_story(Story::new("Login".to_owned(), "".to_owned()), epic_id).unwrap();
+
+        let event: Value = serde_json::from_str(&fs::read_to_string(&pre).unwrap()).unwrap();
+        assert_eq!(event["event"], "story.created");
+        assert_eq!(event["new"]["name"], "Login");
+        assert_eq!(fs::read_to_string(&post).unwrap(), "epic.created\nstory.created\n");
+    }
+
+    #[test]
+    fn failing_post_hook_should_not_undo_the_change() {
+        let db = setup(vec![shell_hook("*", Stage::Post, "exit 1")]);
+
+        assert!(db.create_epic(Epic::new("".to_owned(), "".to_owned())).is_ok());
+        assert_eq!(db.read_db().unwrap().epics.len(), 1);
+    }
+
+    #[test]
+    fn missing_hook_command_should_be_an_error() {
+        let db = setup(vec![Hook {
+            event: "*".to_owned(),
+            stage: Stage::Pre,
+            command: "./there-is-no-such-hook".to_owned(),
+            args: vec![],
+        }]);
+
+        let error = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap_err();
+        assert!(format!("{error:#}").contains("Couldn't run the epic.created hook ./there-is-no-such-hook"));
+        assert!(db.read_db().unwrap().epics.is_empty());
+    }
+}
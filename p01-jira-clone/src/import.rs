@@ -0,0 +1,752 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::db::JiraDatabase;
+use crate::models::{DBState, DbIndex, Epic, Status, Story};
+use crate::query::parse_status;
+
+// the epic that stories without an epic link are put into, since every story here has to be in an epic
+pub const UNSORTED_EPIC_NAME: &str = "Imported from Jira";
+// the same for tasks before the first heading of a Markdown file, and GitHub issues without a milestone
+pub const UNSORTED_MARKDOWN_EPIC_NAME: &str = "Imported from Markdown";
+pub const UNSORTED_GITHUB_EPIC_NAME: &str = "Imported from GitHub";
+
+// the columns of a Jira CSV export that are read, other columns are ignored
+const KEY_COLUMN: &str = "Issue key";
+const ID_COLUMN: &str = "Issue id";
+const TYPE_COLUMN: &str = "Issue Type";
+const SUMMARY_COLUMN: &str = "Summary";
+const STATUS_COLUMN: &str = "Status";
+const DESCRIPTION_COLUMN: &str = "Description";
+const ASSIGNEE_COLUMN: &str = "Assignee";
+// company-managed projects link stories to epics by key, team-managed ones by the id of the parent
+const EPIC_LINK_COLUMNS: [&str; 3] = ["Custom field (Epic Link)", "Parent", "Parent id"];
+const POINTS_COLUMNS: [&str; 2] = ["Custom field (Story Points)", "Custom field (Story point estimate)"];
+
+// the statuses of a default Jira workflow, anything else needs to be mapped with ImportOptions::status_map
+const DEFAULT_STATUS_MAP: [(&str, Status); 10] = [
+    ("to do", Status::Open),
+    ("open", Status::Open),
+    ("backlog", Status::Open),
+    ("selected for development", Status::Open),
+    ("reopened", Status::Open),
+    ("in progress", Status::InProgress),
+    ("in review", Status::InProgress),
+    ("done", Status::Resolved),
+    ("resolved", Status::Resolved),
+    ("closed", Status::Closed),
+];
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ImportOptions {
+    // Jira statuses, lowercase, and what they become, on top of DEFAULT_STATUS_MAP
+    pub status_map: BTreeMap<String, Status>,
+    // the project new epics go into, otherwise the default project
+    pub project: Option<String>,
+    // issues that were imported before, from their Jira key to their key here, which are skipped if they still exist
+    pub known_ids: BTreeMap<String, String>,
+    // works everything out on a copy and reports it without saving anything
+    pub dry_run: bool,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ImportReport {
+    pub epics: usize,
+    pub stories: usize,
+    // items that were imported before and whose status was brought up to date
+    pub updated: usize,
+    // every imported issue from its Jira key to its key here, including the known_ids that were given
+    pub ids: BTreeMap<String, String>,
+    // things that were imported differently than they were in Jira, or left out
+    pub warnings: Vec<String>,
+    // things that stop the import, nothing is saved while there are any
+    pub problems: Vec<String>,
+}
+
+// one row of the export
+#[derive(PartialEq, Debug, Clone, Default)]
+struct JiraIssue {
+    key: String,
+    id: String,
+    issue_type: String,
+    summary: String,
+    status: String,
+    description: String,
+    assignee: String,
+    epic_link: String,
+    points: String,
+}
+
+// the position of each column that is read, None for the optional ones that aren't in the export
+struct Columns {
+    key: usize,
+    issue_type: usize,
+    summary: usize,
+    status: usize,
+    id: Option<usize>,
+    description: Option<usize>,
+    assignee: Option<usize>,
+    epic_link: Vec<usize>,
+    points: Option<usize>,
+}
+
+impl Columns {
+    fn find(headers: &csv::StringRecord) -> Result<Columns> {
+        //exports repeat some columns, like Sprint, so the first one with a name is used
+        let position = |name: &str| headers.iter().position(|header| header.trim_start_matches('\u{feff}').trim() == name);
+        let required = |name: &str| position(name).ok_or_else(|| anyhow!("The export doesn't have an {name:?} column"));
+
+        Ok(Columns {
+            key: required(KEY_COLUMN)?,
+            issue_type: required(TYPE_COLUMN)?,
+            summary: required(SUMMARY_COLUMN)?,
+            status: required(STATUS_COLUMN)?,
+            id: position(ID_COLUMN),
+            description: position(DESCRIPTION_COLUMN),
+            assignee: position(ASSIGNEE_COLUMN),
+            epic_link: EPIC_LINK_COLUMNS.iter().filter_map(|name| position(name)).collect(),
+            points: POINTS_COLUMNS.iter().find_map(|name| position(name)),
+        })
+    }
+
+    fn read(&self, record: &csv::StringRecord) -> JiraIssue {
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column)).unwrap_or_default().trim().to_owned();
+
+        JiraIssue {
+            key: field(Some(self.key)),
+            id: field(self.id),
+            issue_type: field(Some(self.issue_type)),
+            summary: field(Some(self.summary)),
+            status: field(Some(self.status)),
+            description: field(self.description),
+            assignee: field(self.assignee),
+            epic_link: self.epic_link.iter().map(|column| field(Some(*column))).find(|link| !link.is_empty()).unwrap_or_default(),
+            points: field(self.points),
+        }
+    }
+}
+
+fn read_issues(csv_text: &str) -> Result<Vec<JiraIssue>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(csv_text.as_bytes());
+    let columns = Columns::find(reader.headers()?)?;
+
+    let mut issues = vec![];
+    for (row, record) in reader.records().enumerate() {
+        //the header is line 1
+        let record = record.map_err(|error| anyhow!("Line {} of the export can't be read: {error}", row + 2))?;
+        issues.push(columns.read(&record));
+    }
+    Ok(issues)
+}
+
+// the status an issue gets here, from the given map or the default one
+fn map_status(status_map: &BTreeMap<String, Status>, jira_status: &str) -> Option<Status> {
+    let jira_status = jira_status.to_lowercase();
+    status_map
+        .get(&jira_status)
+        .cloned()
+        .or_else(|| DEFAULT_STATUS_MAP.iter().find(|(name, _)| *name == jira_status).map(|(_, status)| status.clone()))
+}
+
+// reads a mapping like "In QA=InProgress" given on the command line
+pub fn parse_status_mapping(text: &str) -> Result<(String, Status)> {
+    let (jira_status, status) = text
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow!("{text} isn't a status mapping, expected <jira status>=<status> like \"In QA=InProgress\""))?;
+    let status = parse_status(status).ok_or_else(|| anyhow!("{status} is not a status, expected Open, InProgress, Resolved or Closed"))?;
+    Ok((jira_status.trim().to_lowercase(), status))
+}
+
+// imports the epics, stories and bugs of a Jira CSV export
+// everything is done on a copy first and saved in one go, so an import with problems leaves the database as it was
+pub fn import_jira_csv(db: &JiraDatabase, csv_text: &str, options: &ImportOptions) -> Result<ImportReport> {
+    let issues = read_issues(csv_text)?;
+    let before = db.read_db()?;
+    let copy = JiraDatabase::in_memory(before.clone(), DBState::default());
+    let mut report = ImportReport {
+        ids: options.known_ids.clone(),
+        ..Default::default()
+    };
+
+    for issue in &issues {
+        if map_status(&options.status_map, &issue.status).is_none() {
+            report.problems.push(format!("{} has the status {:?}, which has to be mapped with --status", issue.key, issue.status));
+        }
+    }
+
+    //issues imported before are left alone, as long as what they became is still here
+    let is_new = |issue: &&JiraIssue| {
+        let known = options.known_ids.get(&issue.key).and_then(|key| before.resolve_key(key));
+        !known.is_some_and(|id| before.epics.contains_key(&id) || before.stories.contains_key(&id))
+    };
+
+    //epics first, so that the stories have somewhere to go
+    let mut epic_ids: BTreeMap<String, DbIndex> = BTreeMap::new();
+    for issue in &issues {
+        if !issue.issue_type.eq_ignore_ascii_case("epic") {
+            continue;
+        }
+
+        let epic_id = match is_new(&issue) {
+            true => {
+                let epic_id = create_epic(&copy, Epic::new(issue.summary.clone(), issue.description.clone()), options)?;
+                set_status(&copy, epic_id, issue, options, |db, id, status| db.update_epic_status(id, status))?;
+                report.ids.insert(issue.key.clone(), copy.read_db()?.display_key(epic_id));
+                report.epics += 1;
+                epic_id
+            }
+            false => before.resolve_key(&options.known_ids[&issue.key]).unwrap(),
+        };
+
+        //stories link to their epic by either of these, depending on the kind of Jira project
+        epic_ids.insert(issue.key.clone(), epic_id);
+        if !issue.id.is_empty() {
+            epic_ids.insert(issue.id.clone(), epic_id);
+        }
+    }
+
+    let mut unsorted_epic = before.epics.iter().find(|(_, epic)| epic.name == UNSORTED_EPIC_NAME).map(|(id, _)| *id);
+    for issue in issues.iter().filter(is_new) {
+        let kind = issue.issue_type.to_lowercase();
+        if kind == "epic" {
+            continue;
+        }
+        //there are no bugs here, so they become stories like the ones they were reported against
+        if kind != "story" && kind != "bug" {
+            report.warnings.push(format!("{} was left out, {} issues aren't imported", issue.key, issue.issue_type));
+            continue;
+        }
+
+        let epic_id = match epic_ids.get(&issue.epic_link) {
+            Some(epic_id) => *epic_id,
+            None => {
+                report.warnings.push(format!("{} isn't in an epic, so it was put in {UNSORTED_EPIC_NAME:?}", issue.key));
+                match unsorted_epic {
+                    Some(epic_id) => epic_id,
+                    None => *unsorted_epic.insert(create_epic(&copy, Epic::new(UNSORTED_EPIC_NAME.to_owned(), "".to_owned()), options)?),
+                }
+            }
+        };
+
+        let story_id = copy.create_story(Story::new(issue.summary.clone(), issue.description.clone()), epic_id)?;
+        set_status(&copy, story_id, issue, options, |db, id, status| db.update_story_status(id, status))?;
+        if !issue.assignee.is_empty() {
+            copy.update_story_assignee(story_id, Some(issue.assignee.clone()))?;
+        }
+        if !issue.points.is_empty() {
+            //Jira keeps points as decimals, which only fit here when they are whole and on the estimate scale
+            let estimate = issue.points.parse::<f64>().ok().filter(|points| points.fract() == 0.0 && *points >= 0.0);
+            if estimate.is_none_or(|points| copy.update_story_estimate(story_id, Some(points as u32)).is_err()) {
+                report.warnings.push(format!("{} was left unestimated, {} points isn't on the estimate scale", issue.key, issue.points));
+            }
+        }
+
+        report.ids.insert(issue.key.clone(), copy.read_db()?.display_key(story_id));
+        report.stories += 1;
+    }
+
+    save(db, &copy, before, options, &report)?;
+    Ok(report)
+}
+
+// saves what was imported into the copy, unless it was a dry run or there were problems
+fn save(db: &JiraDatabase, copy: &JiraDatabase, before: DBState, options: &ImportOptions, report: &ImportReport) -> Result<()> {
+    if options.dry_run || !report.problems.is_empty() {
+        return Ok(());
+    }
+
+    //the import is a single change to the database, which the backup made before it can take back,
+    //so it isn't added to the undo history one item at a time
+    let mut imported = copy.read_db()?;
+    imported.history = before.history;
+    db.save_state(&imported)
+}
+
+fn create_epic(db: &JiraDatabase, epic: Epic, options: &ImportOptions) -> Result<DbIndex> {
+    match &options.project {
+        Some(project) => db.create_epic_in_project(project, epic),
+        None => db.create_epic(epic),
+    }
+}
+
+// issues start out open, so only the ones that aren't need their status set
+fn set_status(
+    db: &JiraDatabase,
+    id: DbIndex,
+    issue: &JiraIssue,
+    options: &ImportOptions,
+    update: fn(&JiraDatabase, DbIndex, Status) -> Result<()>,
+) -> Result<()> {
+    match map_status(&options.status_map, &issue.status) {
+        Some(Status::Open) | None => Ok(()),
+        Some(status) => update(db, id, status),
+    }
+}
+
+// an epic from a file that only has names to tell epics apart, so an epic that already exists is found by its name
+#[derive(PartialEq, Debug, Clone, Default)]
+struct NamedEpic {
+    name: String,
+    description: String,
+    status: Option<Status>,
+    stories: Vec<NamedStory>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+struct NamedStory {
+    name: String,
+    description: String,
+    status: Status,
+    assignee: Option<String>,
+    // what the story is called where it came from, like #12 for a GitHub issue, for the id mapping
+    // a story with one is only found again through ImportOptions::known_ids, and one without by its name in its epic
+    source_key: Option<String>,
+}
+
+// the status something should get on import, None when the status it has is close enough
+// only being done or not is compared, so a story that was started here isn't reopened by importing the same file again
+fn status_change(current: &Status, imported: &Status) -> Option<Status> {
+    let done = |status: &Status| matches!(status, Status::Resolved | Status::Closed);
+    (done(current) != done(imported)).then(|| imported.clone())
+}
+
+// adds the epics and stories that aren't here yet and brings the status of the ones that are up to date,
+// so importing the same file twice gives the same result as importing it once
+fn import_by_name(db: &JiraDatabase, epics: Vec<NamedEpic>, options: &ImportOptions) -> Result<ImportReport> {
+    let before = db.read_db()?;
+    let copy = JiraDatabase::in_memory(before.clone(), DBState::default());
+    let mut report = ImportReport {
+        ids: options.known_ids.clone(),
+        ..Default::default()
+    };
+
+    for named_epic in epics {
+        let db_state = copy.read_db()?;
+        let existing = db_state.epics.iter().filter(|(_, epic)| epic.name == named_epic.name).map(|(id, _)| *id).min();
+
+        let epic_id = match existing {
+            Some(epic_id) => {
+                let change = named_epic.status.as_ref().and_then(|status| status_change(&db_state.epics[&epic_id].status, status));
+                if let Some(status) = change {
+                    copy.update_epic_status(epic_id, status)?;
+                    report.updated += 1;
+                }
+                epic_id
+            }
+            None => {
+                let epic_id = create_epic(&copy, Epic::new(named_epic.name.clone(), named_epic.description.clone()), options)?;
+                if let Some(status) = named_epic.status.filter(|status| *status != Status::Open) {
+                    copy.update_epic_status(epic_id, status)?;
+                }
+                report.epics += 1;
+                epic_id
+            }
+        };
+
+        for named_story in named_epic.stories {
+            let db_state = copy.read_db()?;
+            //names aren't unique, so two issues with the same title mustn't become one story, and a renamed issue is still the same one
+            let existing = match &named_story.source_key {
+                Some(source_key) => options
+                    .known_ids
+                    .get(source_key)
+                    .and_then(|key| db_state.resolve_key(key))
+                    .filter(|story_id| db_state.stories.contains_key(story_id)),
+                None => db_state.epics[&epic_id]
+                    .stories
+                    .iter()
+                    .find(|story_id| db_state.stories.get(story_id).is_some_and(|story| story.name == named_story.name))
+                    .copied(),
+            };
+
+            let story_id = match existing {
+                Some(story_id) => {
+                    if let Some(status) = status_change(&db_state.stories[&story_id].status, &named_story.status) {
+                        copy.update_story_status(story_id, status)?;
+                        report.updated += 1;
+                    }
+                    story_id
+                }
+                None => {
+                    let story_id = copy.create_story(Story::new(named_story.name, named_story.description), epic_id)?;
+                    if named_story.status != Status::Open {
+                        copy.update_story_status(story_id, named_story.status)?;
+                    }
+                    if named_story.assignee.is_some() {
+                        copy.update_story_assignee(story_id, named_story.assignee)?;
+                    }
+                    report.stories += 1;
+                    story_id
+                }
+            };
+
+            if let Some(source_key) = named_story.source_key {
+                report.ids.insert(source_key, copy.read_db()?.display_key(story_id));
+            }
+        }
+    }
+
+    save(db, &copy, before, options, &report)?;
+    Ok(report)
+}
+
+// imports a Markdown task list, where every heading with checkbox items under it becomes an epic
+// and each item a story, closed when it is ticked off
+//   ## Accounts
+//   - [x] Login page
+//     lines indented under an item are its description
+//   - [ ] Password reset
+pub fn import_markdown(db: &JiraDatabase, markdown: &str, options: &ImportOptions) -> Result<ImportReport> {
+    let mut epics: Vec<NamedEpic> = vec![];
+    let mut current = NamedEpic {
+        name: UNSORTED_MARKDOWN_EPIC_NAME.to_owned(),
+        ..Default::default()
+    };
+    let mut in_code_block = false;
+    let mut in_story = false;
+
+    for line in markdown.lines() {
+        //checkboxes in code blocks are examples, not tasks
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+
+        if let Some(heading) = parse_heading(line) {
+            epics.push(std::mem::replace(&mut current, NamedEpic { name: heading, ..Default::default() }));
+            in_story = false;
+        } else if let Some((done, name)) = parse_task(line) {
+            let status = if done { Status::Closed } else { Status::Open };
+            current.stories.push(NamedStory { name, status, ..Default::default() });
+            in_story = true;
+        } else if line.trim().is_empty() {
+            in_story = false;
+        } else if let (true, Some(story)) = (in_story && line.starts_with("  "), current.stories.last_mut()) {
+            story.description = [story.description.as_str(), line.trim()].join("\n").trim_start().to_owned();
+        } else if current.stories.is_empty() && !line.trim_start().starts_with(['-', '*', '+']) {
+            //the text between a heading and its first task describes the epic
+            current.description = [current.description.as_str(), line.trim()].join("\n").trim_start().to_owned();
+        }
+    }
+    epics.push(current);
+
+    //headings without any tasks are only there to organise the document
+    epics.retain(|epic| !epic.stories.is_empty());
+    import_by_name(db, epics, options)
+}
+
+// the text of a line like "## Accounts"
+fn parse_heading(line: &str) -> Option<String> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    match (1..=6).contains(&level) && text.starts_with(' ') {
+        true => Some(text.trim().trim_end_matches('#').trim().to_owned()),
+        false => None,
+    }
+}
+
+// whether a line like "- [x] Login page" is ticked off, and its text
+fn parse_task(line: &str) -> Option<(bool, String)> {
+    let line = line.trim_start();
+    let rest = match line.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        //numbered lists, like "1. [ ] Login page"
+        None => line.trim_start_matches(|c: char| c.is_ascii_digit()).strip_prefix(['.', ')']).filter(|_| line.starts_with(|c: char| c.is_ascii_digit()))?,
+    };
+
+    let rest = rest.strip_prefix(' ')?.trim_start();
+    let done = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    let name = rest[3..].trim();
+    (!name.is_empty()).then(|| (done, name.to_owned()))
+}
+
+// an issue as the GitHub api and `gh issue list --json` give it, only with the fields that are imported
+#[derive(Deserialize)]
+struct GitHubIssue {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    // open or closed, upper case from gh
+    state: String,
+    #[serde(default)]
+    milestone: Option<GitHubMilestone>,
+    #[serde(default)]
+    assignee: Option<GitHubUser>,
+    #[serde(default)]
+    assignees: Vec<GitHubUser>,
+    // the issues endpoint lists pull requests too, with this set
+    #[serde(default)]
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct GitHubMilestone {
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    // gh leaves this out
+    #[serde(default)]
+    state: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    login: String,
+}
+
+// imports a dump of GitHub issues, each milestone becoming an epic with its issues as stories
+pub fn import_github_json(db: &JiraDatabase, json: &str, options: &ImportOptions) -> Result<ImportReport> {
+    let mut issues: Vec<GitHubIssue> =
+        serde_json::from_str(json).context("The file isn't a list of GitHub issues, like the issues api or `gh issue list --json` gives")?;
+    issues.retain(|issue| issue.pull_request.is_none());
+    //the api lists the newest first, oldest first gives the stories their keys in the same order as the issue numbers
+    issues.sort_by_key(|issue| issue.number);
+
+    let closed = |state: &str| if state.eq_ignore_ascii_case("closed") { Status::Closed } else { Status::Open };
+    let mut epics: Vec<NamedEpic> = vec![];
+    for issue in issues {
+        let (name, description, status) = match &issue.milestone {
+            Some(milestone) => (
+                milestone.title.clone(),
+                milestone.description.clone().unwrap_or_default(),
+                milestone.state.as_deref().map(closed),
+            ),
+            None => (UNSORTED_GITHUB_EPIC_NAME.to_owned(), "".to_owned(), None),
+        };
+
+        let epic = match epics.iter_mut().position(|epic| epic.name == name) {
+            Some(position) => &mut epics[position],
+            None => {
+                epics.push(NamedEpic { name, description, status, stories: vec![] });
+                epics.last_mut().unwrap()
+            }
+        };
+
+        let assignee = issue.assignee.or(issue.assignees.into_iter().next()).map(|user| user.login);
+        epic.stories.push(NamedStory {
+            name: issue.title,
+            description: issue.body.unwrap_or_default().replace("\r\n", "\n"),
+            status: closed(&issue.state),
+            assignee,
+            source_key: Some(format!("#{}", issue.number)),
+        });
+    }
+
+    import_by_name(db, epics, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+
+    const EXPORT: &str = "\u{feff}Summary,Issue key,Issue id,Issue Type,Status,Description,Assignee,Custom field (Epic Link),Parent,Custom field (Story Points)
+Accounts,PROJ-1,10001,Epic,In Progress,Everything about logging in,,,,
+Login page,PROJ-2,10002,Story,Done,\"With a \"\"remember me\"\" box
+and a password reset link\",sam,PROJ-1,,3
+Password reset,PROJ-3,10003,Bug,To Do,,,,10001,4
+Search,PROJ-4,10004,Story,In QA,,,,,
+Fix typo,PROJ-5,10005,Sub-task,Done,,,,10002,
+";
+
+    fn setup() -> JiraDatabase {
+        JiraDatabase {
+            database: Box::new(MockDB::new()),
+        }
+    }
+
+    #[test]
+    fn import_should_map_types_epic_links_and_statuses() {
+        let db = setup();
+        let mut options = ImportOptions::default();
+        options.status_map.insert("in qa".to_owned(), Status::InProgress);
+
+        let report = import_jira_csv(&db, EXPORT, &options).unwrap();
+
+        assert!(report.problems.is_empty());
+        assert_eq!((report.epics, report.stories), (1, 3));
+        let db_state = db.read_db().unwrap();
+
+        let accounts = &db_state.epics[&1];
+        assert_eq!(accounts.status, Status::InProgress);
+        assert_eq!(accounts.stories, vec![2, 3]);
+
+        let login = &db_state.stories[&2];
+        assert_eq!(login.description, "With a \"remember me\" box\nand a password reset link");
+        assert_eq!((login.status.clone(), login.assignee.as_deref(), login.estimate), (Status::Resolved, Some("sam"), Some(3)));
+        //4 points isn't on the default scale
+        assert_eq!(db_state.stories[&3].estimate, None);
+
+        assert_eq!(db_state.epics[&4].name, UNSORTED_EPIC_NAME);
+        assert_eq!(db_state.stories[&5].status, Status::InProgress);
+
+        assert_eq!(report.ids["PROJ-4"], "5");
+        assert_eq!(
+            report.warnings,
+            vec![
+                "PROJ-3 was left unestimated, 4 points isn't on the estimate scale",
+                "PROJ-4 isn't in an epic, so it was put in \"Imported from Jira\"",
+                "PROJ-5 was left out, Sub-task issues aren't imported",
+            ]
+        );
+        assert!(db_state.history.undo.is_empty());
+    }
+
+    #[test]
+    fn unmapped_statuses_should_stop_the_import() {
+        let db = setup();
+
+        let report = import_jira_csv(&db, EXPORT, &ImportOptions::default()).unwrap();
+
+        assert_eq!(report.problems, vec!["PROJ-4 has the status \"In QA\", which has to be mapped with --status"]);
+        assert!(db.read_db().unwrap().epics.is_empty());
+    }
+
+    #[test]
+    fn dry_run_should_report_without_saving() {
+        let db = setup();
+        let options = ImportOptions {
+            status_map: BTreeMap::from([parse_status_mapping("In QA=in progress").unwrap()]),
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let report = import_jira_csv(&db, EXPORT, &options).unwrap();
+
+        assert_eq!(report.ids.len(), 4);
+        assert!(db.read_db().unwrap().epics.is_empty());
+    }
+
+    #[test]
+    fn issues_in_the_id_mapping_should_not_be_imported_again() {
+        let db = setup();
+        let mut options = ImportOptions::default();
+        options.status_map.insert("in qa".to_owned(), Status::InProgress);
+        let first = import_jira_csv(&db, EXPORT, &options).unwrap();
+
+        options.known_ids = first.ids.clone();
+        let second = import_jira_csv(&db, EXPORT, &options).unwrap();
+
+        assert_eq!((second.epics, second.stories), (0, 0));
+        assert_eq!(second.ids, first.ids);
+        assert_eq!(db.read_db().unwrap().stories.len(), 3);
+        assert_eq!(db.read_db().unwrap().epics.len(), 2);
+    }
+
+    #[test]
+    fn import_markdown_should_turn_headings_into_epics_and_tasks_into_stories() {
+        let db = setup();
+        let markdown = "# Plans
+
+Loose task list
+
+- [ ] Pick a name
+
+## Accounts
+
+Everything about logging in
+
+- [x] Login page
+  with a remember me box
+- [ ] Password reset
+  1. [X] Send the email
+
+```
+- [ ] not a task
+```
+
+## Notes
+- just a note
+";
+
+        let report = import_markdown(&db, markdown, &ImportOptions::default()).unwrap();
+
+        assert_eq!((report.epics, report.stories), (2, 4));
+        let db_state = db.read_db().unwrap();
+        assert_eq!(db_state.epics[&1].name, "Plans");
+        assert_eq!(db_state.epics[&1].description, "Loose task list");
+        assert_eq!(db_state.epics[&3].name, "Accounts");
+        assert_eq!(db_state.epics[&3].description, "Everything about logging in");
+        assert_eq!(db_state.epics[&3].stories, vec![4, 5, 6]);
+        assert_eq!(db_state.stories[&4].status, Status::Closed);
+        assert_eq!(db_state.stories[&4].description, "with a remember me box");
+        assert_eq!(db_state.stories[&5].status, Status::Open);
+        assert_eq!(db_state.stories[&6].name, "Send the email");
+        assert_eq!(db_state.stories[&6].status, Status::Closed);
+
+        //importing again changes nothing, but a task that was ticked off since closes its story
+        let report = import_markdown(&db, markdown, &ImportOptions::default()).unwrap();
+        assert_eq!((report.epics, report.stories, report.updated), (0, 0, 0));
+        assert_eq!(db.read_db().unwrap(), db_state);
+
+        let report = import_markdown(&db, &markdown.replace("- [ ] Password reset", "- [x] Password reset"), &ImportOptions::default()).unwrap();
+        assert_eq!((report.epics, report.stories, report.updated), (0, 0, 1));
+        assert_eq!(db.read_db().unwrap().stories[&5].status, Status::Closed);
+    }
+
+    #[test]
+    fn import_github_json_should_turn_milestones_into_epics() {
+        let db = setup();
+        let json = r#"[
+            {"number": 3, "title": "Login page", "body": "With a\r\npassword", "state": "closed",
+             "milestone": {"title": "v1", "description": "First release", "state": "open"}, "assignee": {"login": "sam"}},
+            {"number": 2, "title": "Dark mode", "body": null, "state": "open", "milestone": null, "assignee": null},
+            {"number": 4, "title": "Fix login", "state": "OPEN", "milestone": {"title": "v1"}, "assignees": [{"login": "kim"}]},
+            {"number": 5, "title": "A pull request", "state": "open", "pull_request": {"url": ""}}
+        ]"#;
+
+        let report = import_github_json(&db, json, &ImportOptions::default()).unwrap();
+
+        assert_eq!((report.epics, report.stories), (2, 3));
+        assert_eq!(report.ids.clone().into_iter().collect::<Vec<_>>(), vec![
+            ("#2".to_owned(), "2".to_owned()),
+            ("#3".to_owned(), "4".to_owned()),
+            ("#4".to_owned(), "5".to_owned()),
+        ]);
+        let db_state = db.read_db().unwrap();
+        assert_eq!(db_state.epics[&1].name, UNSORTED_GITHUB_EPIC_NAME);
+        assert_eq!(db_state.epics[&3].description, "First release");
+        let login = &db_state.stories[&4];
+        assert_eq!((login.description.as_str(), login.status.clone(), login.assignee.as_deref()), ("With a\npassword", Status::Closed, Some("sam")));
+        assert_eq!(db_state.stories[&5].assignee.as_deref(), Some("kim"));
+
+        let options = ImportOptions {
+            known_ids: report.ids,
+            ..Default::default()
+        };
+        let report = import_github_json(&db, json, &options).unwrap();
+        assert_eq!((report.epics, report.stories, report.updated), (0, 0, 0));
+        assert_eq!(report.ids.len(), 3);
+        assert_eq!(db.read_db().unwrap(), db_state);
+
+        //issues are found again by their number, so a renamed issue is still its story and a new one with the same title isn't
+        let json = r#"[
+            {"number": 4, "title": "Fix the login page", "state": "closed", "milestone": {"title": "v1"}},
+            {"number": 6, "title": "Login page", "state": "open", "milestone": {"title": "v1"}}
+        ]"#;
+        let report = import_github_json(&db, json, &options).unwrap();
+        assert_eq!((report.epics, report.stories, report.updated), (0, 1, 1));
+        let db_state = db.read_db().unwrap();
+        assert_eq!(db_state.stories[&5].status, Status::Closed);
+        assert_eq!(db_state.stories[&4].status, Status::Closed);
+        assert_eq!(report.ids["#6"], "6");
+
+        assert!(import_github_json(&db, "{}", &ImportOptions::default()).is_err());
+    }
+
+    #[test]
+    fn export_without_the_needed_columns_should_be_an_error() {
+        let error = import_jira_csv(&setup(), "Summary,Status\nA,Done\n", &ImportOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "The export doesn't have an \"Issue key\" column");
+    }
+}
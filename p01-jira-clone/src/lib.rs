@@ -0,0 +1,20 @@
+pub mod cli;
+pub mod config;
+pub mod db;
+pub mod directory;
+pub mod event_log;
+pub mod events;
+pub mod export;
+pub mod git;
+pub mod hooks;
+pub mod import;
+pub mod io_utils;
+pub mod merge;
+pub mod models;
+pub mod navigator;
+pub mod query;
+pub mod rules;
+pub mod search;
+pub mod server;
+pub mod ui;
+pub mod webhooks;
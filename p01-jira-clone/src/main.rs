@@ -1,6 +1,104 @@
-mod models;
-mod db;
+use std::io;
+use std::process;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+
+use p01_jira_clone::cli;
+use p01_jira_clone::config::ConfigFiles;
+use p01_jira_clone::io_utils::{get_user_input, wait_for_key_press};
+use p01_jira_clone::navigator::Navigator;
 
 fn main() {
-    println!("Welcome to the Jiralike CLI Application!");
-}
\ No newline at end of file
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    //the user config, the repo's .jira.json and JIRA_WORKSPACE pick which database is opened
+    let mut config_files = match ConfigFiles::from_environment() {
+        Ok(config_files) => config_files,
+        Err(error) => {
+            eprintln!("Error: {error:#}");
+            process::exit(1);
+        }
+    };
+
+    if args.first().is_some_and(|arg| arg == "workspace") {
+        if let Err(error) = cli::workspace(&mut config_files, &args[1..], &mut io::stdout()) {
+            eprintln!("Error: {error}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    let db = match config_files.open_database() {
+        Ok(db) => db,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            process::exit(1);
+        }
+    };
+
+    //--at <time> shows the board as it was at that time, for the command or the interactive ui that follows
+    let db = if args.first().is_some_and(|arg| arg == "--at") {
+        let point_in_time = args
+            .get(1)
+            .ok_or_else(|| anyhow!("Usage: --at <time> [command]"))
+            .and_then(|at| cli::parse_time(at))
+            .and_then(|at| db.at(at));
+        args.drain(..args.len().min(2));
+
+        match point_in_time {
+            Ok(db) => db,
+            Err(error) => {
+                eprintln!("Error: {error}");
+                process::exit(1);
+            }
+        }
+    } else {
+        db
+    };
+    let db = Rc::new(db);
+
+    //any arguments are treated as a one-off command, otherwise the interactive ui is started
+    if !args.is_empty() {
+        if let Err(error) = cli::run(&db, &args, &mut io::stdout()) {
+            eprintln!("Error: {error}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    let display = &config_files.config.display;
+    let mut navigator = Navigator::with_display(Rc::clone(&db), display);
+
+    loop {
+        //clear the terminal and move the cursor back to the top left before drawing the next page
+        if display.clear_screen() {
+            print!("\x1B[2J\x1B[1;1H");
+        }
+
+        let Some(page) = navigator.get_current_page() else {
+            break;
+        };
+
+        if let Err(error) = page.draw_page() {
+            println!("Error rendering page: {error}\nPress any key to continue...");
+            wait_for_key_press();
+        }
+
+        let user_input = get_user_input();
+
+        match page.handle_input(user_input.trim()) {
+            Err(error) => {
+                println!("Error getting user input: {error}\nPress any key to continue...");
+                wait_for_key_press();
+            }
+            Ok(Some(action)) => {
+                if let Err(error) = navigator.handle_action(action) {
+                    println!("Error handling processing user input: {error}\nPress any key to continue...");
+                    wait_for_key_press();
+                }
+            }
+            Ok(None) => {}
+        }
+    }
+}
@@ -0,0 +1,545 @@
+// three-way merging of database files that have been changed on two git branches
+//
+// to have git use it for db.json, add this to .gitattributes:
+//   data/db.json merge=jira
+// and register the driver, where %O, %A and %B are the base, ours and theirs files:
+//   git config merge.jira.driver "p01-jira-clone merge %O %A %B"
+// a directory database writes its own .gitattributes, and only needs its driver registered:
+//   git config merge.jira-json.driver "p01-jira-clone merge-file %O %A %B %P"
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use crate::models::{DBState, DbIndex, TrashedStory};
+
+// a field that both sides changed in different ways, ours is kept in the merged state
+#[derive(PartialEq, Debug, Clone)]
+pub struct Conflict {
+    // where the field is, like stories/5/status
+    pub path: String,
+    pub base: Option<Value>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(deleted)".to_owned(),
+        };
+        write!(
+            f,
+            "{}: base {}, ours {}, theirs {}",
+            self.path,
+            describe(&self.base),
+            describe(&self.ours),
+            describe(&self.theirs)
+        )
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct MergeOutcome {
+    pub merged: DBState,
+    pub conflicts: Vec<Conflict>,
+    // (old id, new id) for every item created on their side that had to be given a new id
+    pub renumbered: Vec<(DbIndex, DbIndex)>,
+}
+
+pub fn merge(base: &DBState, ours: &DBState, theirs: &DBState) -> Result<MergeOutcome> {
+    //with sequential ids both sides hand out the same ids after base.last_item_id,
+    //so anything theirs created with an id ours also created is moved up past every id either side has used
+    let mut theirs = theirs.clone();
+    let mut renumbered = vec![];
+
+    let base_ids = base.used_ids();
+    let ours_new: BTreeSet<DbIndex> = ours.used_ids().difference(&base_ids).copied().collect();
+    let theirs_ids = theirs.used_ids();
+    let colliding: Vec<DbIndex> = theirs_ids.difference(&base_ids).filter(|id| ours_new.contains(id)).copied().collect();
+
+    if !colliding.is_empty() {
+        let mut next_id = ours
+            .used_ids()
+            .union(&theirs_ids)
+            .copied()
+            .chain([ours.last_item_id, theirs.last_item_id])
+            .max()
+            .unwrap_or(0);
+        for old_id in colliding {
+            next_id += 1;
+            renumbered.push((old_id, next_id));
+        }
+        renumber(&mut theirs, &renumbered.iter().copied().collect());
+        theirs.last_item_id = next_id;
+    }
+
+    //the short key numbers can collide in the same way even when the ids don't
+    let ours_numbers: BTreeSet<u32> = ours_new.iter().filter_map(|id| ours.key_number(*id)).collect();
+    let mut next_number = ours.keys.values().chain(theirs.keys.values()).copied().max().unwrap_or(0);
+    for (id, number) in theirs.keys.iter_mut() {
+        if !base_ids.contains(id) && ours_numbers.contains(number) {
+            next_number += 1;
+            *number = next_number;
+        }
+    }
+
+    //and so can the numbers inside each project
+    for (key, project) in theirs.projects.iter_mut() {
+        let Some(ours_project) = ours.projects.get(key) else {
+            continue;
+        };
+        let ours_numbers: BTreeSet<u32> = ours_new.iter().filter_map(|id| ours_project.keys.get(id).copied()).collect();
+        let mut next_number = ours_project.last_number.max(project.last_number);
+        for (id, number) in project.keys.iter_mut() {
+            if !base_ids.contains(id) && ours_numbers.contains(number) {
+                next_number += 1;
+                *number = next_number;
+            }
+        }
+        project.last_number = next_number;
+    }
+
+    let mut conflicts = vec![];
+    let merged = merge_value(
+        "",
+        Some(&serde_json::to_value(base)?),
+        Some(&serde_json::to_value(ours)?),
+        Some(&serde_json::to_value(&theirs)?),
+        &mut conflicts,
+    )
+    .unwrap_or(Value::Null);
+
+    let mut merged: DBState = serde_json::from_value(merged).context("The merged database isn't valid")?;
+
+    merged.last_item_id = ours.last_item_id.max(theirs.last_item_id);
+    for (site, last_id) in theirs.site_last_ids.iter() {
+        let highest = merged.site_last_ids.entry(*site).or_default();
+        *highest = (*highest).max(*last_id);
+    }
+    for (key, project) in merged.projects.iter_mut() {
+        let last_numbers = [ours.projects.get(key), theirs.projects.get(key)];
+        project.last_number = last_numbers.into_iter().flatten().map(|project| project.last_number).max().unwrap_or(0);
+    }
+    //undo history is about the changes made on one branch, so it doesn't make sense to combine two of them
+    merged.history = ours.history.clone();
+    //and the site number belongs to this copy, taking theirs would have both copies hand out the same ids
+    merged.id_strategy = ours.id_strategy.clone();
+    conflicts.retain(|conflict| {
+        let field = conflict.path.split('/').next().unwrap_or_default();
+        let is_counter = matches!(field, "last_item_id" | "site_last_ids")
+            || (field == "projects" && conflict.path.ends_with("/last_number"));
+        field != "history" && field != "id_strategy" && !is_counter
+    });
+
+    Ok(MergeOutcome {
+        merged,
+        conflicts,
+        renumbered,
+    })
+}
+
+// three-way merging of one file of a directory database, which git merges file by file
+// path is where the file is in the repo, like data/db/stories/5.json, and base is None when both sides added the file
+pub fn merge_file(path: &str, base: Option<&Value>, ours: &Value, theirs: &Value) -> (Value, Vec<Conflict>) {
+    let root = path.strip_suffix(".json").unwrap_or(path);
+    let field = root.rsplit('/').next().unwrap_or_default();
+
+    //the site number belongs to this copy, like in merge
+    if field == "id_strategy" {
+        return (ours.clone(), vec![]);
+    }
+
+    let mut conflicts = vec![];
+    let mut merged = merge_value(root, base, Some(ours), Some(theirs), &mut conflicts).unwrap_or(Value::Null);
+
+    //the counters only ever go up, so whichever side went higher is right, like in merge
+    conflicts.retain(|conflict| {
+        let inner = conflict.path.strip_prefix(root).unwrap_or_default();
+        let is_counter = match field {
+            "last_item_id" => inner.is_empty(),
+            "site_last_ids" => !inner.is_empty(),
+            "projects" => inner.ends_with("/last_number"),
+            _ => false,
+        };
+        if !is_counter {
+            return true;
+        }
+
+        let highest = [&conflict.ours, &conflict.theirs].into_iter().flatten().filter_map(Value::as_u64).max();
+        if let (Some(highest), Some(value)) = (highest, merged.pointer_mut(inner)) {
+            *value = highest.into();
+        }
+        false
+    });
+
+    //two copies creating items at the same time both hand out the next number, and the ids are in other files so the
+    //numbers can't be moved up like merge does, which leaves the duplicates for a person to sort out
+    //this is why copies that create items at the same time should use site or time ids, see DirectoryDatabase
+    match field {
+        "keys" if has_duplicate_numbers(Some(&merged)) => conflicts.push(Conflict {
+            path: root.to_owned(),
+            base: base.cloned(),
+            ours: Some(ours.clone()),
+            theirs: Some(theirs.clone()),
+        }),
+        "projects" => {
+            for (key, project) in merged.as_object().into_iter().flatten() {
+                if has_duplicate_numbers(project.get("keys")) {
+                    let keys = |side: Option<&Value>| side.and_then(|side| side.pointer(&format!("/{key}/keys"))).cloned();
+                    conflicts.push(Conflict {
+                        path: format!("{root}/{key}/keys"),
+                        base: keys(base),
+                        ours: keys(Some(ours)),
+                        theirs: keys(Some(theirs)),
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+
+    (merged, conflicts)
+}
+
+// whether a map of ids to key numbers gives two ids the same number
+fn has_duplicate_numbers(keys: Option<&Value>) -> bool {
+    let numbers: Vec<u64> = keys.and_then(Value::as_object).into_iter().flat_map(Map::values).filter_map(Value::as_u64).collect();
+    numbers.iter().collect::<BTreeSet<_>>().len() < numbers.len()
+}
+
+// returns None when the merged result is that the value is gone
+fn merge_value(
+    path: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    conflicts: &mut Vec<Conflict>,
+) -> Option<Value> {
+    if ours == theirs || base == theirs {
+        return ours.cloned();
+    }
+    if base == ours {
+        return theirs.cloned();
+    }
+
+    match (base, ours, theirs) {
+        //both changed the same object, so it is merged field by field
+        (None | Some(Value::Object(_)), Some(Value::Object(ours)), Some(Value::Object(theirs))) => {
+            let empty = Map::new();
+            let base = base.and_then(Value::as_object).unwrap_or(&empty);
+
+            let keys: BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+            let mut merged = Map::new();
+
+            for key in keys {
+                let field_path = if path.is_empty() { key.clone() } else { format!("{path}/{key}") };
+                if let Some(value) = merge_value(&field_path, base.get(key), ours.get(key), theirs.get(key), conflicts) {
+                    merged.insert(key.clone(), value);
+                }
+            }
+
+            Some(Value::Object(merged))
+        }
+        //lists like an epic's stories are merged as sets, keeping our order and adding what theirs added at the end
+        (None | Some(Value::Array(_)), Some(Value::Array(ours)), Some(Value::Array(theirs))) => {
+            let empty = vec![];
+            let base = base.and_then(Value::as_array).unwrap_or(&empty);
+
+            //anything theirs removed is dropped from ours
+            let mut merged: Vec<Value> = ours
+                .iter()
+                .filter(|value| !base.contains(value) || theirs.contains(value))
+                .cloned()
+                .collect();
+            for value in theirs {
+                if !base.contains(value) && !merged.contains(value) {
+                    merged.push(value.clone());
+                }
+            }
+
+            Some(Value::Array(merged))
+        }
+        _ => {
+            conflicts.push(Conflict {
+                path: path.to_owned(),
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            });
+            //when one side deleted what the other changed, the change is kept so nothing is lost
+            ours.or(theirs).cloned()
+        }
+    }
+}
+
+// changes ids everywhere they are used, both as keys and inside items that refer to other items
+fn renumber(db_state: &mut DBState, ids: &BTreeMap<DbIndex, DbIndex>) {
+    let new_id = |id: DbIndex| *ids.get(&id).unwrap_or(&id);
+
+    db_state.epics = db_state
+        .epics
+        .drain()
+        .map(|(id, mut epic)| {
+            epic.stories.iter_mut().for_each(|story_id| *story_id = new_id(*story_id));
+            (new_id(id), epic)
+        })
+        .collect();
+    db_state.stories = db_state.stories.drain().map(|(id, story)| (new_id(id), story)).collect();
+    db_state.sprints = db_state
+        .sprints
+        .drain()
+        .map(|(id, mut sprint)| {
+            sprint.stories.iter_mut().for_each(|story_id| *story_id = new_id(*story_id));
+            (new_id(id), sprint)
+        })
+        .collect();
+    db_state.archived_ids = db_state.archived_ids.iter().map(|id| new_id(*id)).collect();
+    db_state.keys = db_state.keys.iter().map(|(id, number)| (new_id(*id), *number)).collect();
+    for project in db_state.projects.values_mut() {
+        project.keys = project.keys.iter().map(|(id, number)| (new_id(*id), *number)).collect();
+    }
+
+    let renumber_trashed_story = |trashed: &mut TrashedStory| {
+        trashed.epic_id = new_id(trashed.epic_id);
+        trashed.sprints.iter_mut().for_each(|sprint_id| *sprint_id = new_id(*sprint_id));
+    };
+
+    db_state.trash.stories = db_state
+        .trash
+        .stories
+        .drain()
+        .map(|(id, mut trashed)| {
+            renumber_trashed_story(&mut trashed);
+            (new_id(id), trashed)
+        })
+        .collect();
+    db_state.trash.epics = db_state
+        .trash
+        .epics
+        .drain()
+        .map(|(id, mut trashed)| {
+            trashed.epic.stories.iter_mut().for_each(|story_id| *story_id = new_id(*story_id));
+            trashed.stories = trashed
+                .stories
+                .drain()
+                .map(|(story_id, mut story)| {
+                    renumber_trashed_story(&mut story);
+                    (new_id(story_id), story)
+                })
+                .collect();
+            (new_id(id), trashed)
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Epic, IdStrategy, Project, Status, Story};
+
+    fn base() -> DBState {
+        let mut db_state = DBState {
+            last_item_id: 2,
+            ..Default::default()
+        };
+        let mut epic = Epic::new("Accounts".to_owned(), "".to_owned());
+        epic.stories.push(2);
+        db_state.epics.insert(1, epic);
+        db_state.stories.insert(2, Story::new("Login page".to_owned(), "".to_owned()));
+        db_state
+    }
+
+    fn add_story(db_state: &mut DBState, name: &str) -> DbIndex {
+        db_state.last_item_id += 1;
+        db_state.stories.insert(db_state.last_item_id, Story::new(name.to_owned(), "".to_owned()));
+        db_state.epics.get_mut(&1).unwrap().stories.push(db_state.last_item_id);
+        db_state.last_item_id
+    }
+
+    #[test]
+    fn merge_should_combine_changes_to_different_fields() {
+        let mut ours = base();
+        ours.stories.get_mut(&2).unwrap().status = Status::Closed;
+        let mut theirs = base();
+        theirs.stories.get_mut(&2).unwrap().assignee = Some("sam".to_owned());
+
+        let outcome = merge(&base(), &ours, &theirs).unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.merged.stories[&2].status, Status::Closed);
+        assert_eq!(outcome.merged.stories[&2].assignee, Some("sam".to_owned()));
+    }
+
+    #[test]
+    fn merge_should_renumber_colliding_ids() {
+        let mut ours = base();
+        add_story(&mut ours, "Ours");
+        let mut theirs = base();
+        add_story(&mut theirs, "Theirs");
+
+        let outcome = merge(&base(), &ours, &theirs).unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.renumbered, vec![(3, 4)]);
+        assert_eq!(outcome.merged.last_item_id, 4);
+        assert_eq!(outcome.merged.stories[&3].name, "Ours");
+        assert_eq!(outcome.merged.stories[&4].name, "Theirs");
+        assert_eq!(outcome.merged.epics[&1].stories, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn merge_should_not_renumber_site_ids() {
+        let mut base = base();
+        base.id_strategy = IdStrategy::Site { site: 1 };
+        let mut ours = base.clone();
+        let ours_id = ours.next_id(None).unwrap();
+        let mut theirs = base.clone();
+        theirs.id_strategy = IdStrategy::Site { site: 2 };
+        let theirs_id = theirs.next_id(None).unwrap();
+        ours.stories.insert(ours_id, Story::new("Ours".to_owned(), "".to_owned()));
+        theirs.stories.insert(theirs_id, Story::new("Theirs".to_owned(), "".to_owned()));
+
+        let outcome = merge(&base, &ours, &theirs).unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        assert!(outcome.renumbered.is_empty());
+        assert_eq!(outcome.merged.stories.len(), 3);
+        assert_eq!(outcome.merged.id_strategy, IdStrategy::Site { site: 1 });
+
+        //both sides gave their story the next key number, so theirs is moved up
+        assert_eq!(outcome.merged.display_key(ours_id), "3");
+        assert_eq!(outcome.merged.display_key(theirs_id), "4");
+    }
+
+    #[test]
+    fn merge_should_renumber_colliding_project_keys() {
+        let mut base = base();
+        base.id_strategy = IdStrategy::Site { site: 1 };
+        base.projects.insert("WEB".to_owned(), Project::new("Website".to_owned(), "".to_owned()));
+        let mut ours = base.clone();
+        let ours_id = ours.next_id(Some("WEB")).unwrap();
+        let mut theirs = base.clone();
+        theirs.id_strategy = IdStrategy::Site { site: 2 };
+        let theirs_id = theirs.next_id(Some("WEB")).unwrap();
+        ours.stories.insert(ours_id, Story::new("Ours".to_owned(), "".to_owned()));
+        theirs.stories.insert(theirs_id, Story::new("Theirs".to_owned(), "".to_owned()));
+
+        let outcome = merge(&base, &ours, &theirs).unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.merged.display_key(ours_id), "WEB-1");
+        assert_eq!(outcome.merged.display_key(theirs_id), "WEB-2");
+        assert_eq!(outcome.merged.projects["WEB"].last_number, 2);
+        assert_eq!(outcome.merged.id_strategy, IdStrategy::Site { site: 1 });
+    }
+
+    #[test]
+    fn merge_should_keep_ids_when_only_one_side_created_items() {
+        let ours = base();
+        let mut theirs = base();
+        add_story(&mut theirs, "Theirs");
+
+        let outcome = merge(&base(), &ours, &theirs).unwrap();
+
+        assert!(outcome.renumbered.is_empty());
+        assert_eq!(outcome.merged, theirs);
+    }
+
+    #[test]
+    fn merge_should_apply_deletions() {
+        let ours = base();
+        let mut theirs = base();
+        theirs.stories.remove(&2);
+        theirs.epics.get_mut(&1).unwrap().stories.clear();
+
+        let outcome = merge(&base(), &ours, &theirs).unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        assert!(outcome.merged.stories.is_empty());
+    }
+
+    #[test]
+    fn merge_should_report_conflicts_and_keep_ours() {
+        let mut ours = base();
+        ours.stories.get_mut(&2).unwrap().status = Status::Closed;
+        let mut theirs = base();
+        theirs.stories.get_mut(&2).unwrap().status = Status::Resolved;
+
+        let outcome = merge(&base(), &ours, &theirs).unwrap();
+
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!(
+            outcome.conflicts[0].to_string(),
+            r#"stories/2/status: base "Open", ours "Closed", theirs "Resolved""#
+        );
+        assert_eq!(outcome.merged.stories[&2].status, Status::Closed);
+    }
+
+    #[test]
+    fn merge_file_should_merge_the_files_of_a_directory_database() {
+        let value = |json: &str| serde_json::from_str::<Value>(json).unwrap();
+
+        let (merged, conflicts) = merge_file("db/last_item_id.json", Some(&value("4")), &value("6"), &value("5"));
+        assert_eq!((merged, conflicts), (value("6"), vec![]));
+
+        let (merged, conflicts) = merge_file("db/id_strategy.json", None, &value(r#"{"Site": {"site": 1}}"#), &value(r#"{"Site": {"site": 2}}"#));
+        assert_eq!((merged, conflicts), (value(r#"{"Site": {"site": 1}}"#), vec![]));
+
+        let (merged, conflicts) = merge_file("db/site_last_ids.json", Some(&value(r#"{"1": 4}"#)), &value(r#"{"1": 5}"#), &value(r#"{"1": 6}"#));
+        assert_eq!((merged, conflicts), (value(r#"{"1": 6}"#), vec![]));
+
+        let (merged, conflicts) = merge_file(
+            "db/projects.json",
+            Some(&value(r#"{"WEB": {"keys": {"1": 1}, "last_number": 1}}"#)),
+            &value(r#"{"WEB": {"keys": {"1": 1, "4294967297": 2}, "last_number": 2}}"#),
+            &value(r#"{"WEB": {"keys": {"1": 1, "8589934593": 3}, "last_number": 3}}"#),
+        );
+        assert!(conflicts.is_empty());
+        assert_eq!(merged, value(r#"{"WEB": {"keys": {"1": 1, "4294967297": 2, "8589934593": 3}, "last_number": 3}}"#));
+
+        //both sides giving their new story the same number can't be fixed from this file alone
+        let (_, conflicts) = merge_file(
+            "db/projects.json",
+            Some(&value(r#"{"WEB": {"keys": {"1": 1}, "last_number": 1}}"#)),
+            &value(r#"{"WEB": {"keys": {"1": 1, "4294967297": 2}, "last_number": 2}}"#),
+            &value(r#"{"WEB": {"keys": {"1": 1, "8589934593": 2}, "last_number": 2}}"#),
+        );
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "db/projects/WEB/keys");
+
+        let (_, conflicts) = merge_file("db/keys.json", Some(&value("{}")), &value(r#"{"4294967297": 3}"#), &value(r#"{"8589934593": 3}"#));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "db/keys");
+
+        //both sides adding a story to the same epic touches the same file, which is merged like any other list
+        let (merged, conflicts) = merge_file(
+            "db/epics/1.json",
+            Some(&value(r#"{"name": "Accounts", "stories": [2]}"#)),
+            &value(r#"{"name": "Accounts", "stories": [2, 3]}"#),
+            &value(r#"{"name": "Accounts", "stories": [2, 4]}"#),
+        );
+        assert!(conflicts.is_empty());
+        assert_eq!(merged, value(r#"{"name": "Accounts", "stories": [2, 3, 4]}"#));
+
+        let (_, conflicts) = merge_file("db/stories/2.json", None, &value(r#"{"name": "Ours"}"#), &value(r#"{"name": "Theirs"}"#));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "db/stories/2/name");
+    }
+
+    #[test]
+    fn merge_should_keep_an_item_that_one_side_deleted_and_the_other_changed() {
+        let mut ours = base();
+        ours.stories.remove(&2);
+        let mut theirs = base();
+        theirs.stories.get_mut(&2).unwrap().status = Status::Closed;
+
+        let outcome = merge(&base(), &ours, &theirs).unwrap();
+
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!(outcome.conflicts[0].path, "stories/2");
+        assert_eq!(outcome.merged.stories[&2].status, Status::Closed);
+    }
+}
@@ -1,17 +1,88 @@
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::BuildHasher;
+use std::fmt::Display;
 
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 //derive the appropriate traits
-#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+//Eq and Hash are needed so that Status can be used as a HashMap key when totalling story points per status
+//Default is Open, which is what a new epic or story starts as
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone, Default)]
 pub enum Status {
+    #[default]
     Open,
     InProgress,
     Resolved,
     Closed,
 }
 
-pub type DbIndex = u16;
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Open => write!(f, "OPEN"),
+            Status::InProgress => write!(f, "IN PROGRESS"),
+            Status::Resolved => write!(f, "RESOLVED"),
+            Status::Closed => write!(f, "CLOSED"),
+        }
+    }
+}
+
+impl Status {
+    // every status in workflow order, used wherever stories are grouped into columns by status
+    pub fn all() -> [Status; 4] {
+        [Status::Open, Status::InProgress, Status::Resolved, Status::Closed]
+    }
+
+    // the statuses either side of this one in the workflow, None at either end
+    pub fn previous(&self) -> Option<Status> {
+        let index = Status::all().iter().position(|status| status == self)?;
+        index.checked_sub(1).map(|i| Status::all()[i].clone())
+    }
+
+    pub fn next(&self) -> Option<Status> {
+        let index = Status::all().iter().position(|status| status == self)?;
+        Status::all().get(index + 1).cloned()
+    }
+}
+
+// the kinds of items that are stored in the database, used wherever epics and stories are handled together
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum ItemKind {
+    Epic,
+    Story,
+}
+
+impl Display for ItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ItemKind::Epic => write!(f, "EPIC"),
+            ItemKind::Story => write!(f, "STORY"),
+        }
+    }
+}
+
+// what the user chose to do on a page, carried out by the Navigator
+#[derive(PartialEq, Debug, Clone)]
+pub enum Action {
+    NavigateToBoard { epic_id: Option<DbIndex> },
+    NavigateToSprint { sprint_id: DbIndex },
+    NavigatePreviousPage,
+    MoveStory { story_id: DbIndex, status: Status },
+    Exit,
+}
+
+// u64 so that there is room for ids that are unique across copies of the database, see IdStrategy
+pub type DbIndex = u64;
+
+pub type StoryPoints = u32;
+
+// the scale used when a database file doesn't specify its own, which is the usual planning poker sequence
+pub fn default_estimate_scale() -> Vec<StoryPoints> {
+    vec![0, 1, 2, 3, 5, 8, 13, 21]
+}
 
 //derive the appropriate traits
 #[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
@@ -40,19 +111,372 @@ pub struct Story {
     pub name: String,
     pub description: String,
     pub status: Status,
+    // story points, None until the story has been estimated
+    // serde(default) lets older db files without this field still be read
+    #[serde(default)]
+    pub estimate: Option<StoryPoints>,
+    // who is working on the story, if anyone
+    #[serde(default)]
+    pub assignee: Option<String>,
+    // hashes of the git commits whose messages mention the story, oldest first
+    #[serde(default)]
+    pub commits: Vec<String>,
+}
+
+// the fields of an epic that one update can change, None leaves a field as it is
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct EpicUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub status: Option<Status>,
+}
+
+// the same for a story, where Some(None) clears the assignee or the estimate
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct StoryUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub status: Option<Status>,
+    pub assignee: Option<Option<String>>,
+    pub estimate: Option<Option<StoryPoints>>,
 }
 
 impl Story {
     pub fn new(name: String, description: String) -> Self {
-        // by default the status should be set to open
+        // by default the status should be set to open and the story is unestimated
         Story {
             name,
             description,
             status: Status::Open,
+            estimate: None,
+            assignee: None,
+            commits: vec![],
+        }
+    }
+}
+
+// story point totals for all the stories in one epic
+#[derive(PartialEq, Debug, Default)]
+pub struct EpicPoints {
+    pub total: StoryPoints,
+    pub by_status: HashMap<Status, StoryPoints>,
+    pub unestimated: usize,
+}
+
+impl EpicPoints {
+    // points for stories that are finished, meaning Resolved or Closed
+    pub fn completed(&self) -> StoryPoints {
+        [Status::Resolved, Status::Closed]
+            .iter()
+            .filter_map(|status| self.by_status.get(status))
+            .fold(0, |completed: StoryPoints, points| completed.saturating_add(*points))
+    }
+}
+
+// stories grouped by status, one entry per status in Status::all() order, each holding (story_id, story) pairs
+pub type StatusGroups = Vec<(Status, Vec<(DbIndex, Story)>)>;
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub enum SprintState {
+    Planned,
+    Active,
+    Completed,
+}
+
+impl Display for SprintState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SprintState::Planned => write!(f, "PLANNED"),
+            SprintState::Active => write!(f, "ACTIVE"),
+            SprintState::Completed => write!(f, "COMPLETED"),
         }
     }
 }
 
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Sprint {
+    pub name: String,
+    pub goal: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub state: SprintState,
+    pub stories: Vec<DbIndex>,
+}
+
+impl Sprint {
+    pub fn new(name: String, goal: String, start: NaiveDate, end: NaiveDate) -> Self {
+        //a new sprint is planned and has no stories until they are added to it
+        Sprint {
+            name,
+            goal,
+            start,
+            end,
+            state: SprintState::Planned,
+            stories: vec![],
+        }
+    }
+}
+
+// a deleted story, along with everything needed to put it back exactly where it was
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct TrashedStory {
+    pub story: Story,
+    pub epic_id: DbIndex,
+    // the index the story had in its epic's stories vector
+    pub position: usize,
+    pub sprints: Vec<DbIndex>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+// a deleted epic goes into the trash together with all of its stories, so that restoring it brings them all back
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct TrashedEpic {
+    pub epic: Epic,
+    pub stories: HashMap<DbIndex, TrashedStory>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+pub fn default_trash_retention_days() -> Option<u32> {
+    Some(30)
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Trash {
+    pub epics: HashMap<DbIndex, TrashedEpic>,
+    pub stories: HashMap<DbIndex, TrashedStory>,
+    // items are purged for good once they have been in the trash this long, None keeps them forever
+    #[serde(default = "default_trash_retention_days")]
+    pub retention_days: Option<u32>,
+}
+
+impl Default for Trash {
+    fn default() -> Self {
+        Trash {
+            epics: HashMap::new(),
+            stories: HashMap::new(),
+            retention_days: default_trash_retention_days(),
+        }
+    }
+}
+
+// one step of the undo/redo history
+// each operation is the inverse of a change that was made, so applying it reverses that change,
+// and applying it gives back the operation that would redo the change
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub enum Operation {
+    RemoveEpic { epic_id: DbIndex },
+    InsertEpic { epic_id: DbIndex, epic: Epic },
+    RemoveStory { story_id: DbIndex },
+    InsertStory { story_id: DbIndex, story: TrashedStory },
+    TrashItem { id: DbIndex },
+    RestoreItem { id: DbIndex },
+    SetEpicStatus { epic_id: DbIndex, status: Status },
+    SetStoryStatus { story_id: DbIndex, status: Status },
+    SetStoryEstimate { story_id: DbIndex, estimate: Option<StoryPoints> },
+    SetStoryAssignee { story_id: DbIndex, assignee: Option<String> },
+    // edits are recorded as SetEpic and SetStory now, these are only kept so that histories saved before still undo
+    SetEpicDetails { epic_id: DbIndex, name: String, description: String },
+    SetStoryDetails { story_id: DbIndex, name: String, description: String },
+    RemoveSprint { sprint_id: DbIndex },
+    InsertSprint { sprint_id: DbIndex, sprint: Sprint },
+    // puts sprints back the way they were, for changes to their stories or state, which can touch two sprints at once
+    SetSprints { sprints: Vec<(DbIndex, Sprint)> },
+    // None removes the filter
+    SetFilter { name: String, filter: Option<SavedFilter> },
+    SetEstimateScale { scale: Vec<StoryPoints> },
+    // puts a story back the way it was, for updates that change several of its fields at once
+    SetStory { story_id: DbIndex, story: Story },
+    // the same for an epic, apart from its list of stories, which is left as it is
+    SetEpic { epic_id: DbIndex, epic: Epic },
+    ArchiveEpics { epic_ids: Vec<DbIndex> },
+    // sprint_stories are the (sprint, story) pairs that archiving took out of planned and active sprints
+    UnarchiveEpics { epic_ids: Vec<DbIndex>, sprint_stories: Vec<(DbIndex, DbIndex)> },
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::RemoveEpic { epic_id } => write!(f, "remove epic {epic_id}"),
+            Operation::InsertEpic { epic_id, .. } => write!(f, "add epic {epic_id}"),
+            Operation::RemoveStory { story_id } => write!(f, "remove story {story_id}"),
+            Operation::InsertStory { story_id, .. } => write!(f, "add story {story_id}"),
+            Operation::TrashItem { id } => write!(f, "delete {id}"),
+            Operation::RestoreItem { id } => write!(f, "restore {id} from the trash"),
+            Operation::SetEpicStatus { epic_id, status } => write!(f, "set status of epic {epic_id} to {status}"),
+            Operation::SetStoryStatus { story_id, status } => write!(f, "set status of story {story_id} to {status}"),
+            Operation::SetStoryEstimate { story_id, estimate: Some(points) } => {
+                write!(f, "set estimate of story {story_id} to {points}")
+            }
+            Operation::SetStoryEstimate { story_id, estimate: None } => write!(f, "clear estimate of story {story_id}"),
+            Operation::SetStoryAssignee { story_id, assignee: Some(assignee) } => {
+                write!(f, "assign story {story_id} to {assignee}")
+            }
+            Operation::SetStoryAssignee { story_id, assignee: None } => write!(f, "unassign story {story_id}"),
+            Operation::SetEpicDetails { epic_id, .. } => write!(f, "edit epic {epic_id}"),
+            Operation::SetStoryDetails { story_id, .. } => write!(f, "edit story {story_id}"),
+            Operation::RemoveSprint { sprint_id } => write!(f, "remove sprint {sprint_id}"),
+            Operation::InsertSprint { sprint_id, .. } => write!(f, "add sprint {sprint_id}"),
+            Operation::SetSprints { sprints } => {
+                let ids: Vec<DbIndex> = sprints.iter().map(|(sprint_id, _)| *sprint_id).collect();
+                write!(f, "change sprint {}", join_ids(&ids))
+            }
+            Operation::SetFilter { name, filter: Some(_) } => write!(f, "save filter {name}"),
+            Operation::SetFilter { name, filter: None } => write!(f, "delete filter {name}"),
+            Operation::SetEstimateScale { scale } => write!(f, "set the estimate scale to {scale:?}"),
+            Operation::SetStory { story_id, .. } => write!(f, "update story {story_id}"),
+            Operation::SetEpic { epic_id, .. } => write!(f, "update epic {epic_id}"),
+            Operation::ArchiveEpics { epic_ids } => write!(f, "archive epic {}", join_ids(epic_ids)),
+            Operation::UnarchiveEpics { epic_ids, .. } => write!(f, "take epic {} out of the archive", join_ids(epic_ids)),
+        }
+    }
+}
+
+fn join_ids(ids: &[DbIndex]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    ids.join(" and ")
+}
+
+impl Operation {
+    // whether applying the operation reads and writes the archive as well as the live db
+    pub fn uses_archive(&self) -> bool {
+        matches!(self, Operation::ArchiveEpics { .. } | Operation::UnarchiveEpics { .. })
+    }
+}
+
+pub fn default_history_limit() -> usize {
+    50
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct History {
+    pub undo: Vec<Operation>,
+    pub redo: Vec<Operation>,
+    // the most operations kept on the undo stack, the oldest are dropped first
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            undo: vec![],
+            redo: vec![],
+            limit: default_history_limit(),
+        }
+    }
+}
+
+impl History {
+    // adds the inverse of a change that was just made, which means anything that had been undone can't be redone anymore
+    pub fn record(&mut self, inverse: Operation) {
+        self.redo.clear();
+        self.push_undo(inverse);
+    }
+
+    pub fn push_undo(&mut self, operation: Operation) {
+        self.undo.push(operation);
+        if self.undo.len() > self.limit {
+            let excess = self.undo.len() - self.limit;
+            self.undo.drain(..excess);
+        }
+    }
+}
+
+// how many copies of the previous db file are kept when it is overwritten
+// backups beyond either limit are pruned after each write, and None means no limit
+#[derive(PartialEq, Debug, Clone)]
+pub struct BackupConfig {
+    pub keep: Option<usize>,
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            keep: Some(10),
+            max_age_days: Some(30),
+        }
+    }
+}
+
+// a backup of the db file, the id is its timestamp and is what the backup commands take
+#[derive(PartialEq, Debug, Clone)]
+pub struct Backup {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+// a query saved under a name so it can be run again later
+// the query text is stored rather than its parsed form, so saved filters keep working when the query module changes
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct SavedFilter {
+    pub query: String,
+    // pinned filters are shown on the home page
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+// a product with its own epics, whose epics and stories are shown with keys like WEB-17
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Project {
+    pub name: String,
+    pub description: String,
+    // the last number given out in this project's key sequence
+    #[serde(default)]
+    pub last_number: u32,
+    // the key number of every epic and story that was created in this project, which is also how an item is known to belong to it
+    #[serde(default)]
+    pub keys: BTreeMap<DbIndex, u32>,
+}
+
+impl Project {
+    pub fn new(name: String, description: String) -> Self {
+        Project {
+            name,
+            description,
+            last_number: 0,
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+// project keys and key prefixes are capital letters and digits, starting with a letter, like WEB
+pub fn is_valid_key_prefix(prefix: &str) -> bool {
+    prefix.starts_with(|c: char| c.is_ascii_uppercase())
+        && prefix.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+// how new ids are handed out
+// sequential ids are short, but two offline copies of the database hand out the same ones
+// site ids put a number that is different for each copy above the sequence, so copies never collide
+// and merging keeps each copy's own strategy, so the site number set in one copy never ends up in another
+// time ids are ULID-style, the time in milliseconds followed by random bits, so they need no setup at all
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
+pub enum IdStrategy {
+    #[default]
+    Sequential,
+    Site { site: u16 },
+    Time,
+}
+
+impl Display for IdStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdStrategy::Sequential => write!(f, "sequential"),
+            IdStrategy::Site { site } => write!(f, "site {site}"),
+            IdStrategy::Time => write!(f, "time"),
+        }
+    }
+}
+
+// the bits below the site number in a site id
+const SITE_SHIFT: u32 = 32;
+// the random bits below the time in a time id
+// milliseconds fit in 42 bits until 2109, so time ids stay within the 53 bits that a JSON number can hold exactly in
+// JavaScript, which is what the API and webhooks hand them to, and above the 48 bits that site ids can reach
+const TIME_SHIFT: u32 = 11;
+
 //derive the appropriate traits
 #[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
 pub struct DBState {
@@ -61,5 +485,183 @@ pub struct DBState {
     pub last_item_id: DbIndex,
     pub epics: HashMap<DbIndex, Epic>,
     pub stories: HashMap<DbIndex, Story>,
-    // u16 used deliberately as a number with high enough max number but can save a few bits vs using u32
+    // the allowed story point values, kept sorted ascending
+    #[serde(default = "default_estimate_scale")]
+    pub estimate_scale: Vec<StoryPoints>,
+    // sprint ids come from the same last_item_id counter as epics and stories
+    #[serde(default)]
+    pub sprints: HashMap<DbIndex, Sprint>,
+    // saved filters by name
+    #[serde(default)]
+    pub filters: HashMap<String, SavedFilter>,
+    // deleted epics and stories waiting to be restored or purged
+    #[serde(default)]
+    pub trash: Trash,
+    // undo and redo stacks, kept in the db file so that they work across runs of the program
+    #[serde(default)]
+    pub history: History,
+    // ids of epics and stories that have been moved to the archive, which are never handed out again
+    #[serde(default)]
+    pub archived_ids: BTreeSet<DbIndex>,
+    #[serde(default)]
+    pub id_strategy: IdStrategy,
+    // the last id each site handed out, which like last_item_id only goes up so that purged ids are never handed out again
+    #[serde(default)]
+    pub site_last_ids: BTreeMap<u16, DbIndex>,
+    // items are shown as PREFIX-42 when this is set, and just as 42 when it isn't
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+    // the short number shown for items whose id is too long to show, which is any item made with a site or time id
+    // other items are shown with their id as the number, and numbers are never reused
+    #[serde(default)]
+    pub keys: BTreeMap<DbIndex, u32>,
+    // projects by their key
+    #[serde(default)]
+    pub projects: BTreeMap<String, Project>,
+    // the project new epics go into when no project is given
+    #[serde(default)]
+    pub default_project: Option<String>,
+}
+
+impl Default for DBState {
+    fn default() -> Self {
+        DBState {
+            last_item_id: 0,
+            epics: HashMap::new(),
+            stories: HashMap::new(),
+            estimate_scale: default_estimate_scale(),
+            sprints: HashMap::new(),
+            filters: HashMap::new(),
+            trash: Trash::default(),
+            history: History::default(),
+            archived_ids: BTreeSet::new(),
+            id_strategy: IdStrategy::default(),
+            site_last_ids: BTreeMap::new(),
+            key_prefix: None,
+            keys: BTreeMap::new(),
+            projects: BTreeMap::new(),
+            default_project: None,
+        }
+    }
+}
+
+impl DBState {
+    // every id that has been handed out and is still in use somewhere, including the trash and the archive
+    pub fn used_ids(&self) -> BTreeSet<DbIndex> {
+        let mut ids: BTreeSet<DbIndex> = BTreeSet::new();
+
+        ids.extend(self.epics.keys());
+        ids.extend(self.stories.keys());
+        ids.extend(self.sprints.keys());
+        ids.extend(self.trash.stories.keys());
+        ids.extend(&self.archived_ids);
+        for (epic_id, trashed) in &self.trash.epics {
+            ids.insert(*epic_id);
+            ids.extend(trashed.stories.keys());
+        }
+
+        ids
+    }
+
+    // hands out the next id according to the id strategy
+    // inside a project the item gets the next number in the project's sequence, otherwise it gets a short key number when it needs one
+    // a site that has handed out every id in its range gets an error, rather than an id from the next site's range
+    pub fn next_id(&mut self, project: Option<&str>) -> Result<DbIndex> {
+        let used = self.used_ids();
+
+        let id = match self.id_strategy {
+            IdStrategy::Sequential => self.last_item_id + 1,
+            IdStrategy::Site { site } => {
+                let first = (site as DbIndex) << SITE_SHIFT;
+                let last = first + ((1 << SITE_SHIFT) - 1);
+                let highest = used.range(first..=last).next_back().into_iter().chain(self.site_last_ids.get(&site)).max();
+                match highest {
+                    Some(id) if *id == last => return Err(anyhow!("Site {site} has used up all of its ids")),
+                    Some(id) => id + 1,
+                    None => first + 1,
+                }
+            }
+            IdStrategy::Time => loop {
+                let millis = Utc::now().timestamp_millis() as DbIndex;
+                let random = RandomState::new().hash_one(Utc::now()) & ((1 << TIME_SHIFT) - 1);
+                let id = (millis << TIME_SHIFT) | random;
+                if !used.contains(&id) {
+                    break id;
+                }
+            },
+        };
+
+        if let Some(project) = project.and_then(|key| self.projects.get_mut(key)) {
+            project.last_number += 1;
+            project.keys.insert(id, project.last_number);
+        } else if self.key_number(id).is_none() {
+            let numbers = used.iter().filter_map(|id| self.key_number(*id)).chain(self.keys.values().copied());
+            let number = numbers.max().unwrap_or(0) + 1;
+            self.keys.insert(id, number);
+        }
+
+        if let IdStrategy::Site { site } = self.id_strategy {
+            self.site_last_ids.insert(site, id);
+        }
+        self.last_item_id = id;
+        Ok(id)
+    }
+
+    // the short number for an item, which is its id unless it was given a key number
+    pub fn key_number(&self, id: DbIndex) -> Option<u32> {
+        match self.keys.get(&id) {
+            Some(number) => Some(*number),
+            None => u32::try_from(id).ok(),
+        }
+    }
+
+    // the key of the project an item was created in
+    pub fn project_of(&self, id: DbIndex) -> Option<&String> {
+        self.projects.iter().find(|(_, project)| project.keys.contains_key(&id)).map(|(key, _)| key)
+    }
+
+    // how an item is shown to people, like WEB-42
+    pub fn display_key(&self, id: DbIndex) -> String {
+        if let Some(key) = self.project_of(id) {
+            return format!("{key}-{}", self.projects[key].keys[&id]);
+        }
+
+        match (self.key_number(id), &self.key_prefix) {
+            (Some(number), Some(prefix)) => format!("{prefix}-{number}"),
+            (Some(number), None) => number.to_string(),
+            (None, _) => id.to_string(),
+        }
+    }
+
+    // finds the id for something typed in by a person, which can be a key like WEB-42, a bare key number or an id
+    pub fn resolve_key(&self, text: &str) -> Option<DbIndex> {
+        let text = text.trim();
+
+        //a project key like WEB-17
+        if let Some((prefix, number)) = text.rsplit_once('-') {
+            let project = self.projects.iter().find(|(key, _)| key.eq_ignore_ascii_case(prefix)).map(|(_, project)| project);
+
+            if let (Some(project), Ok(number)) = (project, number.parse::<u32>()) {
+                return project.keys.iter().find(|(_, project_number)| **project_number == number).map(|(id, _)| *id);
+            }
+        }
+        let number = match &self.key_prefix {
+            Some(prefix) => match text.split_once('-') {
+                Some((typed_prefix, number)) if typed_prefix.eq_ignore_ascii_case(prefix) => number,
+                _ => text,
+            },
+            None => text,
+        };
+
+        let used = self.used_ids();
+
+        if let Ok(number) = number.parse::<u32>() {
+            if let Some(id) = used.iter().find(|id| self.key_number(**id) == Some(number)) {
+                return Some(*id);
+            }
+        }
+
+        //a full id always works, whatever its key is
+        text.parse::<DbIndex>().ok().filter(|id| used.contains(id))
+    }
 }
\ No newline at end of file
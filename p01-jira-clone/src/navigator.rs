@@ -0,0 +1,153 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::config::{DisplayConfig, DEFAULT_BOARD_COLUMN_WIDTH};
+use crate::db::JiraDatabase;
+use crate::models::Action;
+use crate::ui::{BoardPage, HomePage, Page, SprintPage};
+
+pub struct Navigator {
+    pages: Vec<Box<dyn Page>>,
+    db: Rc<JiraDatabase>,
+    board_column_width: usize,
+}
+
+impl Navigator {
+    pub fn new(db: Rc<JiraDatabase>) -> Self {
+        //the home page is always at the bottom of the page stack
+        Self {
+            pages: vec![Box::new(HomePage { db: Rc::clone(&db) })],
+            db,
+            board_column_width: DEFAULT_BOARD_COLUMN_WIDTH,
+        }
+    }
+
+    pub fn with_display(db: Rc<JiraDatabase>, display: &DisplayConfig) -> Self {
+        Self {
+            board_column_width: display.board_column_width(),
+            ..Self::new(db)
+        }
+    }
+
+    pub fn get_current_page(&self) -> Option<&dyn Page> {
+        self.pages.last().map(|page| page.as_ref())
+    }
+
+    pub fn handle_action(&mut self, action: Action) -> Result<()> {
+        match action {
+            Action::NavigateToBoard { epic_id } => {
+                let mut board = BoardPage::new(epic_id, Rc::clone(&self.db));
+                board.column_width = self.board_column_width;
+                self.pages.push(Box::new(board));
+            }
+            Action::NavigateToSprint { sprint_id } => {
+                let mut sprint = SprintPage::new(sprint_id, Rc::clone(&self.db));
+                sprint.column_width = self.board_column_width;
+                self.pages.push(Box::new(sprint));
+            }
+            Action::NavigatePreviousPage => {
+                //the home page can't be popped, quitting is done through Exit instead
+                if self.pages.len() > 1 {
+                    self.pages.pop();
+                }
+            }
+            Action::MoveStory { story_id, status } => {
+                self.db.update_story_status(story_id, status)?;
+            }
+            Action::Exit => {
+                self.pages.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    // Private functions used for testing
+
+    #[cfg(test)]
+    fn get_page_count(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::test_utils::MockDB;
+    use crate::models::{Epic, Status, Story};
+
+    use super::*;
+
+    #[test]
+    fn should_start_on_home_page() {
+        let db = Rc::new(JiraDatabase {
+            database: Box::new(MockDB::new()),
+        });
+        let nav = Navigator::new(db);
+
+        assert_eq!(nav.get_page_count(), 1);
+        assert!(nav.get_current_page().is_some());
+    }
+
+    #[test]
+    fn handle_action_should_navigate_pages() {
+        let db = Rc::new(JiraDatabase {
+            database: Box::new(MockDB::new()),
+        });
+
+        let mut nav = Navigator::new(db);
+
+        nav.handle_action(Action::NavigateToBoard { epic_id: None }).unwrap();
+        assert_eq!(nav.get_page_count(), 2);
+
+        nav.handle_action(Action::NavigatePreviousPage).unwrap();
+        assert_eq!(nav.get_page_count(), 1);
+
+        nav.handle_action(Action::NavigatePreviousPage).unwrap();
+        assert_eq!(nav.get_page_count(), 1);
+
+        nav.handle_action(Action::NavigateToSprint { sprint_id: 1 }).unwrap();
+        assert_eq!(nav.get_page_count(), 2);
+    }
+
+    #[test]
+    fn handle_action_should_clear_pages_on_exit() {
+        let db = Rc::new(JiraDatabase {
+            database: Box::new(MockDB::new()),
+        });
+
+        let mut nav = Navigator::new(db);
+
+        nav.handle_action(Action::NavigateToBoard { epic_id: None }).unwrap();
+        nav.handle_action(Action::Exit).unwrap();
+
+        assert_eq!(nav.get_page_count(), 0);
+        assert!(nav.get_current_page().is_none());
+    }
+
+    #[test]
+    fn handle_action_should_move_story() {
+        let db = Rc::new(JiraDatabase {
+            database: Box::new(MockDB::new()),
+        });
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        let story_id = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+
+        let mut nav = Navigator::new(Rc::clone(&db));
+
+        let result = nav.handle_action(Action::MoveStory {
+            story_id,
+            status: Status::InProgress,
+        });
+        assert!(result.is_ok());
+
+        let db_state = db.read_db().unwrap();
+        assert_eq!(db_state.stories.get(&story_id).unwrap().status, Status::InProgress);
+
+        let result = nav.handle_action(Action::MoveStory {
+            story_id: 999,
+            status: Status::InProgress,
+        });
+        assert!(result.is_err());
+    }
+}
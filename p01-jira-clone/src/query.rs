@@ -0,0 +1,823 @@
+// A small JQL-like query language for filtering stories, for example:
+//
+//     status in (Open, InProgress) and epic = 4 and name ~ "login" order by id desc
+//
+// A query is turned into tokens, the tokens are parsed into a Query (the AST), and the Query is then run against
+// a DBState to get the matching story ids. Keywords and field names are case-insensitive.
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use crate::models::{DBState, DbIndex, Status, Story};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct QueryError {
+    // 1-based position in the query, counted in chars, of where the problem starts
+    pub column: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        QueryError {
+            column,
+            message: message.into(),
+        }
+    }
+
+    // the query with a ^ under the offending column, for showing to the user
+    pub fn pointer(&self, query: &str) -> String {
+        format!("{query}\n{}^", " ".repeat(self.column.saturating_sub(1)))
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Field {
+    Id,
+    Epic,
+    Name,
+    Description,
+    Status,
+    Estimate,
+    Assignee,
+    Sprint,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name.to_lowercase().as_str() {
+            "id" => Some(Field::Id),
+            "epic" => Some(Field::Epic),
+            "name" => Some(Field::Name),
+            "description" => Some(Field::Description),
+            "status" => Some(Field::Status),
+            "estimate" => Some(Field::Estimate),
+            "assignee" => Some(Field::Assignee),
+            "sprint" => Some(Field::Sprint),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Field::Id | Field::Epic | Field::Estimate | Field::Sprint)
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self, Field::Name | Field::Description | Field::Assignee)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Contains,
+    NotContains,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Value {
+    Number(u64),
+    Text(String),
+    Status(Status),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare { field: Field, op: CompareOp, value: Value },
+    In { field: Field, values: Vec<Value> },
+    IsEmpty { field: Field },
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Query {
+    // None when the query has no conditions, which matches every story
+    pub filter: Option<Expr>,
+    pub order_by: Vec<(Field, SortDirection)>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum TokenKind {
+    Word(String),
+    Text(String),
+    Number(u64),
+    Op(CompareOp),
+    LeftParen,
+    RightParen,
+    Comma,
+    End,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let (kind, length) = match c {
+            '(' => (TokenKind::LeftParen, 1),
+            ')' => (TokenKind::RightParen, 1),
+            ',' => (TokenKind::Comma, 1),
+            '=' => (TokenKind::Op(CompareOp::Equal), 1),
+            '~' => (TokenKind::Op(CompareOp::Contains), 1),
+            '!' => match chars.get(i + 1) {
+                Some('=') => (TokenKind::Op(CompareOp::NotEqual), 2),
+                Some('~') => (TokenKind::Op(CompareOp::NotContains), 2),
+                _ => return Err(QueryError::new(column, "expected != or !~")),
+            },
+            '<' | '>' => {
+                let or_equal = chars.get(i + 1) == Some(&'=');
+                let op = match (c, or_equal) {
+                    ('<', false) => CompareOp::Less,
+                    ('<', true) => CompareOp::LessOrEqual,
+                    ('>', false) => CompareOp::Greater,
+                    _ => CompareOp::GreaterOrEqual,
+                };
+                (TokenKind::Op(op), if or_equal { 2 } else { 1 })
+            }
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '"')
+                    .ok_or_else(|| QueryError::new(column, "unclosed quote"))?;
+                let text: String = chars[i + 1..i + 1 + end].iter().collect();
+                (TokenKind::Text(text), end + 2)
+            }
+            c if c.is_ascii_digit() => {
+                let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                let number = digits
+                    .parse()
+                    .map_err(|_| QueryError::new(column, "number is too large"))?;
+                (TokenKind::Number(number), digits.len())
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let word: String = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '-')
+                    .collect();
+                let length = word.chars().count();
+                (TokenKind::Word(word), length)
+            }
+            c => return Err(QueryError::new(column, format!("unexpected character '{c}'"))),
+        };
+
+        tokens.push(Token { kind, column });
+        i += length;
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        column: chars.len() + 1,
+    });
+
+    Ok(tokens)
+}
+
+pub fn parse(query: &str) -> Result<Query, QueryError> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        position: 0,
+    };
+    parser.parse_query()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+        if token.kind != TokenKind::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    // consumes the keyword if it is next, returning whether it was there
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), QueryError> {
+        if self.accept_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(QueryError::new(self.peek().column, format!("expected {}", keyword.to_uppercase())))
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, description: &str) -> Result<(), QueryError> {
+        if self.peek().kind == kind {
+            self.next();
+            Ok(())
+        } else {
+            Err(QueryError::new(self.peek().column, format!("expected {description}")))
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query, QueryError> {
+        let mut query = Query::default();
+
+        if self.peek().kind != TokenKind::End && !self.peek_keyword("order") {
+            query.filter = Some(self.parse_or()?);
+        }
+
+        if self.accept_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let field = self.parse_field()?;
+                let direction = if self.accept_keyword("desc") {
+                    SortDirection::Descending
+                } else {
+                    self.accept_keyword("asc");
+                    SortDirection::Ascending
+                };
+                query.order_by.push((field, direction));
+
+                if self.peek().kind != TokenKind::Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+
+        if self.peek().kind != TokenKind::End {
+            return Err(QueryError::new(self.peek().column, "expected AND, OR, ORDER BY or the end of the query"));
+        }
+
+        Ok(query)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_and()?;
+        while self.accept_keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_unary()?;
+        while self.accept_keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if self.accept_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        if self.peek().kind == TokenKind::LeftParen {
+            self.next();
+            let expr = self.parse_or()?;
+            self.expect(TokenKind::RightParen, ")")?;
+            return Ok(expr);
+        }
+
+        self.parse_condition()
+    }
+
+    fn parse_field(&mut self) -> Result<Field, QueryError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Word(word) => Field::from_name(word)
+                .ok_or_else(|| QueryError::new(token.column, format!("unknown field '{word}'"))),
+            _ => Err(QueryError::new(token.column, "expected a field name")),
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<Expr, QueryError> {
+        let field = self.parse_field()?;
+
+        if self.accept_keyword("is") {
+            let negated = self.accept_keyword("not");
+            self.expect_keyword("empty")?;
+            let expr = Expr::IsEmpty { field };
+            return Ok(if negated { Expr::Not(Box::new(expr)) } else { expr });
+        }
+
+        let negated = self.accept_keyword("not");
+        if negated || self.peek_keyword("in") {
+            self.expect_keyword("in")?;
+            self.expect(TokenKind::LeftParen, "(")?;
+
+            let mut values = vec![self.parse_value(field)?];
+            while self.peek().kind == TokenKind::Comma {
+                self.next();
+                values.push(self.parse_value(field)?);
+            }
+            self.expect(TokenKind::RightParen, ")")?;
+
+            let expr = Expr::In { field, values };
+            return Ok(if negated { Expr::Not(Box::new(expr)) } else { expr });
+        }
+
+        let token = self.next();
+        let TokenKind::Op(op) = token.kind else {
+            return Err(QueryError::new(token.column, "expected an operator (=, !=, ~, !~, <, <=, >, >=, IN or IS)"));
+        };
+
+        let allowed = match op {
+            CompareOp::Equal | CompareOp::NotEqual => true,
+            CompareOp::Contains | CompareOp::NotContains => field.is_text(),
+            _ => field.is_numeric(),
+        };
+        if !allowed {
+            return Err(QueryError::new(token.column, "this operator can't be used with this field"));
+        }
+
+        let value = self.parse_value(field)?;
+        Ok(Expr::Compare { field, op, value })
+    }
+
+    // values are checked against the field here so that mistakes are reported with a column instead of silently matching nothing
+    fn parse_value(&mut self, field: Field) -> Result<Value, QueryError> {
+        let token = self.next();
+
+        match (field, &token.kind) {
+            (Field::Status, TokenKind::Word(word) | TokenKind::Text(word)) => parse_status(word)
+                .map(Value::Status)
+                .ok_or_else(|| QueryError::new(token.column, format!("unknown status '{word}'"))),
+            (Field::Status, _) => Err(QueryError::new(token.column, "expected a status")),
+            //keys like WEB-3 are kept as text until the query is run against a db that knows them, see resolve_keys
+            (Field::Id | Field::Epic, TokenKind::Word(key)) => Ok(Value::Text(key.clone())),
+            (field, TokenKind::Number(number)) if field.is_numeric() => Ok(Value::Number(*number)),
+            (field, _) if field.is_numeric() => Err(QueryError::new(token.column, "expected a number")),
+            (_, TokenKind::Word(text) | TokenKind::Text(text)) => Ok(Value::Text(text.clone())),
+            (_, TokenKind::Number(number)) => Ok(Value::Text(number.to_string())),
+            _ => Err(QueryError::new(token.column, "expected a value")),
+        }
+    }
+}
+
+pub(crate) fn parse_status(text: &str) -> Option<Status> {
+    let normalized: String = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    Status::all()
+        .into_iter()
+        .find(|status| format!("{status:?}").to_lowercase() == normalized)
+}
+
+// the value of one field on one story, used both to check conditions and to sort
+#[derive(PartialEq, Debug, Clone)]
+enum FieldValue {
+    Numbers(Vec<u64>),
+    Text(Option<String>),
+    Status(Status),
+}
+
+fn field_value(db_state: &DBState, story_id: DbIndex, story: &Story, field: Field) -> FieldValue {
+    match field {
+        Field::Id => FieldValue::Numbers(vec![story_id]),
+        Field::Epic => FieldValue::Numbers(
+            db_state
+                .epics
+                .iter()
+                .filter(|(_, epic)| epic.stories.contains(&story_id))
+                .map(|(id, _)| *id)
+                .collect(),
+        ),
+        Field::Sprint => FieldValue::Numbers(
+            db_state
+                .sprints
+                .iter()
+                .filter(|(_, sprint)| sprint.stories.contains(&story_id))
+                .map(|(id, _)| *id)
+                .collect(),
+        ),
+        Field::Estimate => FieldValue::Numbers(story.estimate.map(|points| points as u64).into_iter().collect()),
+        Field::Name => FieldValue::Text(Some(story.name.clone())),
+        Field::Description => FieldValue::Text(Some(story.description.clone())),
+        Field::Assignee => FieldValue::Text(story.assignee.clone()),
+        Field::Status => FieldValue::Status(story.status.clone()),
+    }
+}
+
+fn compare(actual: &FieldValue, op: CompareOp, expected: &Value) -> bool {
+    match (actual, expected) {
+        (FieldValue::Numbers(numbers), Value::Number(expected)) => match op {
+            CompareOp::NotEqual => !numbers.contains(expected),
+            op => numbers.iter().any(|number| match op {
+                CompareOp::Equal => number == expected,
+                CompareOp::Less => number < expected,
+                CompareOp::LessOrEqual => number <= expected,
+                CompareOp::Greater => number > expected,
+                CompareOp::GreaterOrEqual => number >= expected,
+                _ => false,
+            }),
+        },
+        (FieldValue::Text(text), Value::Text(expected)) => {
+            let text = text.as_deref().unwrap_or("").to_lowercase();
+            let expected = expected.to_lowercase();
+            match op {
+                CompareOp::Equal => text == expected,
+                CompareOp::NotEqual => text != expected,
+                CompareOp::Contains => text.contains(&expected),
+                CompareOp::NotContains => !text.contains(&expected),
+                _ => false,
+            }
+        }
+        (FieldValue::Status(status), Value::Status(expected)) => match op {
+            CompareOp::Equal => status == expected,
+            CompareOp::NotEqual => status != expected,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn matches(db_state: &DBState, story_id: DbIndex, story: &Story, expr: &Expr) -> bool {
+    match expr {
+        Expr::And(left, right) => {
+            matches(db_state, story_id, story, left) && matches(db_state, story_id, story, right)
+        }
+        Expr::Or(left, right) => {
+            matches(db_state, story_id, story, left) || matches(db_state, story_id, story, right)
+        }
+        Expr::Not(expr) => !matches(db_state, story_id, story, expr),
+        Expr::Compare { field, op, value } => {
+            compare(&field_value(db_state, story_id, story, *field), *op, value)
+        }
+        Expr::In { field, values } => {
+            let actual = field_value(db_state, story_id, story, *field);
+            values.iter().any(|value| compare(&actual, CompareOp::Equal, value))
+        }
+        Expr::IsEmpty { field } => match field_value(db_state, story_id, story, *field) {
+            FieldValue::Numbers(numbers) => numbers.is_empty(),
+            FieldValue::Text(text) => text.is_none_or(|text| text.is_empty()),
+            FieldValue::Status(_) => false,
+        },
+    }
+}
+
+// orders two values of the same field, with empty values after everything else when sorting ascending
+fn compare_field_values(a: &FieldValue, b: &FieldValue) -> Ordering {
+    match (a, b) {
+        (FieldValue::Numbers(a), FieldValue::Numbers(b)) => match (a.iter().min(), b.iter().min()) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        },
+        (FieldValue::Text(a), FieldValue::Text(b)) => match (a, b) {
+            (Some(a), Some(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        },
+        (FieldValue::Status(a), FieldValue::Status(b)) => {
+            let position = |status: &Status| Status::all().iter().position(|s| s == status);
+            position(a).cmp(&position(b))
+        }
+        _ => Ordering::Equal,
+    }
+}
+
+// swaps every key the query gives for an id or epic, like WEB-3, for the id it stands for
+// returns the first key that doesn't stand for anything
+pub fn resolve_keys(query: &mut Query, db_state: &DBState) -> Result<(), String> {
+    fn resolve(expr: &mut Expr, db_state: &DBState) -> Result<(), String> {
+        let (field, values) = match expr {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                resolve(left, db_state)?;
+                return resolve(right, db_state);
+            }
+            Expr::Not(expr) => return resolve(expr, db_state),
+            Expr::Compare { field, value, .. } => (*field, std::slice::from_mut(value)),
+            Expr::In { field, values } => (*field, values.as_mut_slice()),
+            Expr::IsEmpty { .. } => return Ok(()),
+        };
+
+        if !matches!(field, Field::Id | Field::Epic) {
+            return Ok(());
+        }
+        for value in values {
+            if let Value::Text(key) = value {
+                let id = db_state.resolve_key(key).ok_or_else(|| key.clone())?;
+                *value = Value::Number(id);
+            }
+        }
+        Ok(())
+    }
+
+    match &mut query.filter {
+        Some(filter) => resolve(filter, db_state),
+        None => Ok(()),
+    }
+}
+
+// every number the query compares the given field against, used to check that the epics or sprints it names still exist
+pub fn referenced_ids(query: &Query, field: Field) -> Vec<u64> {
+    fn collect(expr: &Expr, field: Field, ids: &mut Vec<u64>) {
+        match expr {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                collect(left, field, ids);
+                collect(right, field, ids);
+            }
+            Expr::Not(expr) => collect(expr, field, ids),
+            Expr::Compare {
+                field: f,
+                value: Value::Number(id),
+                ..
+            } if *f == field => ids.push(*id),
+            Expr::In { field: f, values } if *f == field => {
+                ids.extend(values.iter().filter_map(|value| match value {
+                    Value::Number(id) => Some(*id),
+                    _ => None,
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    let mut ids = vec![];
+    if let Some(filter) = &query.filter {
+        collect(filter, field, &mut ids);
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+// every name the query says a text field has to be, with = or in, used to check that the assignees it names have stories
+// ~ is left out, since part of a name doesn't have to match anyone in particular, and so is anything under not or compared
+// with !=, since those still match stories when nobody has the name
+pub fn referenced_names(query: &Query, field: Field) -> Vec<String> {
+    fn collect(expr: &Expr, field: Field, names: &mut Vec<String>) {
+        match expr {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                collect(left, field, names);
+                collect(right, field, names);
+            }
+            Expr::Compare {
+                field: f,
+                op: CompareOp::Equal,
+                value: Value::Text(name),
+            } if *f == field => names.push(name.clone()),
+            Expr::In { field: f, values } if *f == field => {
+                names.extend(values.iter().filter_map(|value| match value {
+                    Value::Text(name) => Some(name.clone()),
+                    _ => None,
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    let mut names = vec![];
+    if let Some(filter) = &query.filter {
+        collect(filter, field, &mut names);
+    }
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+// the ids of every story matching the query, in the order asked for by the query or by id if it has no ORDER BY
+pub fn run(db_state: &DBState, query: &Query) -> Vec<DbIndex> {
+    let mut story_ids: Vec<DbIndex> = db_state
+        .stories
+        .iter()
+        .filter(|(id, story)| {
+            query
+                .filter
+                .as_ref()
+                .is_none_or(|filter| matches(db_state, **id, story, filter))
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    story_ids.sort_by(|a, b| {
+        let story_a = &db_state.stories[a];
+        let story_b = &db_state.stories[b];
+
+        query
+            .order_by
+            .iter()
+            .map(|(field, direction)| {
+                let ordering = compare_field_values(
+                    &field_value(db_state, *a, story_a, *field),
+                    &field_value(db_state, *b, story_b, *field),
+                );
+                match direction {
+                    SortDirection::Ascending => ordering,
+                    SortDirection::Descending => ordering.reverse(),
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a.cmp(b))
+    });
+
+    story_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Epic, Story};
+
+    fn setup() -> DBState {
+        let mut db_state = DBState::default();
+
+        let mut epic_1 = Epic::new("Accounts".to_owned(), "".to_owned());
+        epic_1.stories = vec![2, 3];
+        let mut epic_4 = Epic::new("Billing".to_owned(), "".to_owned());
+        epic_4.stories = vec![5, 6];
+        db_state.epics.insert(1, epic_1);
+        db_state.epics.insert(4, epic_4);
+
+        let mut login = Story::new("Login page".to_owned(), "".to_owned());
+        login.estimate = Some(3);
+        login.assignee = Some("sam".to_owned());
+
+        let mut signup = Story::new("Sign up".to_owned(), "".to_owned());
+        signup.status = Status::InProgress;
+        signup.estimate = Some(5);
+
+        let mut invoice = Story::new("Invoice login check".to_owned(), "".to_owned());
+        invoice.status = Status::InProgress;
+
+        let mut refunds = Story::new("Refunds".to_owned(), "".to_owned());
+        refunds.status = Status::Closed;
+        refunds.estimate = Some(8);
+
+        db_state.stories.insert(2, login);
+        db_state.stories.insert(3, signup);
+        db_state.stories.insert(5, invoice);
+        db_state.stories.insert(6, refunds);
+        db_state.last_item_id = 6;
+
+        db_state
+    }
+
+    fn run_query(query: &str) -> Vec<DbIndex> {
+        run(&setup(), &parse(query).unwrap())
+    }
+
+    #[test]
+    fn parse_should_build_the_ast() {
+        let query = parse(r#"status in (Open, InProgress) and epic = 4 and name ~ "login" order by id desc"#).unwrap();
+
+        let expected = Query {
+            filter: Some(Expr::And(
+                Box::new(Expr::And(
+                    Box::new(Expr::In {
+                        field: Field::Status,
+                        values: vec![Value::Status(Status::Open), Value::Status(Status::InProgress)],
+                    }),
+                    Box::new(Expr::Compare {
+                        field: Field::Epic,
+                        op: CompareOp::Equal,
+                        value: Value::Number(4),
+                    }),
+                )),
+                Box::new(Expr::Compare {
+                    field: Field::Name,
+                    op: CompareOp::Contains,
+                    value: Value::Text("login".to_owned()),
+                }),
+            )),
+            order_by: vec![(Field::Id, SortDirection::Descending)],
+        };
+
+        assert_eq!(query, expected);
+    }
+
+    #[test]
+    fn parse_should_accept_an_empty_query() {
+        assert_eq!(parse("").unwrap(), Query::default());
+        assert_eq!(parse("  ORDER BY name").unwrap().order_by, vec![(Field::Name, SortDirection::Ascending)]);
+    }
+
+    #[test]
+    fn parse_should_report_the_column_of_errors() {
+        let cases = [
+            ("nmae = login", 1, "unknown field 'nmae'"),
+            ("status = Done", 10, "unknown status 'Done'"),
+            ("estimate = four", 12, "expected a number"),
+            (r#"epic = "four""#, 8, "expected a number"),
+            ("name < 4", 6, "this operator can't be used with this field"),
+            (r#"name ~ "login"#, 8, "unclosed quote"),
+            ("status in (Open", 16, "expected )"),
+            ("epic = 4 status = Open", 10, "expected AND, OR, ORDER BY or the end of the query"),
+            ("id = 4 order id", 14, "expected BY"),
+            ("id @ 4", 4, "unexpected character '@'"),
+        ];
+
+        for (query, column, message) in cases {
+            assert_eq!(
+                parse(query),
+                Err(QueryError::new(column, message)),
+                "query: {query}"
+            );
+        }
+    }
+
+    #[test]
+    fn query_error_pointer_should_point_at_the_column() {
+        let error = parse("status = Done").unwrap_err();
+
+        assert_eq!(error.pointer("status = Done"), "status = Done\n         ^");
+        assert_eq!(error.to_string(), "column 10: unknown status 'Done'");
+    }
+
+    #[test]
+    fn run_should_filter_stories() {
+        assert_eq!(run_query(""), vec![2, 3, 5, 6]);
+        assert_eq!(run_query("status in (Open, InProgress)"), vec![2, 3, 5]);
+        assert_eq!(run_query("status = \"in progress\" and epic = 4"), vec![5]);
+        assert_eq!(run_query("name ~ LOGIN"), vec![2, 5]);
+        assert_eq!(run_query("name !~ login"), vec![3, 6]);
+        assert_eq!(run_query("estimate >= 5 or assignee = sam"), vec![2, 3, 6]);
+        assert_eq!(run_query("not (epic = 1)"), vec![5, 6]);
+        assert_eq!(run_query("status not in (Closed)"), vec![2, 3, 5]);
+        assert_eq!(run_query("estimate is empty"), vec![5]);
+        assert_eq!(run_query("assignee is not empty"), vec![2]);
+    }
+
+    #[test]
+    fn run_should_order_stories() {
+        assert_eq!(run_query("order by id desc"), vec![6, 5, 3, 2]);
+        assert_eq!(run_query("order by status desc, name"), vec![6, 5, 3, 2]);
+        assert_eq!(run_query("order by estimate desc"), vec![5, 6, 3, 2]);
+        assert_eq!(run_query("order by estimate"), vec![2, 3, 6, 5]);
+        assert_eq!(
+            run_query(r#"status in (Open, InProgress) and epic = 4 and name ~ "login" order by id desc"#),
+            vec![5]
+        );
+    }
+
+    #[test]
+    fn resolve_keys_should_swap_keys_for_ids() {
+        let mut db_state = setup();
+        db_state.key_prefix = Some("WEB".to_owned());
+
+        let mut query = parse("epic = web-4 or id in (WEB-2, 3)").unwrap();
+        resolve_keys(&mut query, &db_state).unwrap();
+        assert_eq!(run(&db_state, &query), vec![2, 3, 5, 6]);
+        assert_eq!(referenced_ids(&query, Field::Epic), vec![4]);
+
+        let mut query = parse("epic = WEB-4 and id = WEB-40").unwrap();
+        assert_eq!(resolve_keys(&mut query, &db_state), Err("WEB-40".to_owned()));
+    }
+
+    #[test]
+    fn referenced_ids_should_find_every_comparison_with_the_field() {
+        let query = parse("(epic = 4 or epic in (1, 9)) and not sprint = 7 and id > 2 and epic != 4").unwrap();
+
+        assert_eq!(referenced_ids(&query, Field::Epic), vec![1, 4, 9]);
+        assert_eq!(referenced_ids(&query, Field::Sprint), vec![7]);
+        assert_eq!(referenced_ids(&query, Field::Estimate), Vec::<u64>::new());
+    }
+}
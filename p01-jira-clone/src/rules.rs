@@ -0,0 +1,448 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::{Database, JiraDatabase};
+use crate::events::changes;
+use crate::models::{Backup, DBState, DbIndex, Epic, Status, Story, StoryPoints};
+use crate::query::parse_status;
+use crate::webhooks::Delivery;
+
+// how long a rule may run for one event when its config doesn't say
+pub const DEFAULT_TIME_LIMIT_MS: u64 = 250;
+// rules can react to each other's changes, this stops two rules from changing something back and forth forever
+const MAX_ROUNDS: usize = 10;
+
+// a rhai script that is run for every event it is interested in and can make changes of its own, set up in the config file
+// scripts get the event as `event`, with its new value as event["new"] since new is a keyword in rhai
+// they can only see and change the database through the functions registered in engine()
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Rule {
+    // an event type like story.status_changed, or * for every event
+    pub event: String,
+    // relative paths are relative to the config file
+    pub script: PathBuf,
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+}
+
+// a change a script asked for, which is made through JiraDatabase after the script has finished
+// so that it is checked and can be undone like any other change
+#[derive(PartialEq, Debug, Clone)]
+enum RuleAction {
+    SetEpicStatus { epic_id: DbIndex, status: Status },
+    SetStoryStatus { story_id: DbIndex, status: Status },
+    SetStoryAssignee { story_id: DbIndex, assignee: Option<String> },
+    SetStoryEstimate { story_id: DbIndex, estimate: Option<StoryPoints> },
+    CreateStory { epic_id: DbIndex, name: String, description: String },
+}
+
+impl RuleAction {
+    fn apply(self, db: &JiraDatabase) -> Result<()> {
+        match self {
+            RuleAction::SetEpicStatus { epic_id, status } => db.update_epic_status(epic_id, status),
+            RuleAction::SetStoryStatus { story_id, status } => db.update_story_status(story_id, status),
+            RuleAction::SetStoryAssignee { story_id, assignee } => db.update_story_assignee(story_id, assignee),
+            RuleAction::SetStoryEstimate { story_id, estimate } => db.update_story_estimate(story_id, estimate),
+            RuleAction::CreateStory { epic_id, name, description } => db.create_story(Story::new(name, description), epic_id).map(|_| ()),
+        }
+    }
+}
+
+// what scripts get for an epic or a story, a copy taken when the rule started
+#[derive(Debug, Clone)]
+struct ScriptEpic {
+    key: String,
+    epic: Epic,
+    stories: Array,
+}
+
+#[derive(Debug, Clone)]
+struct ScriptStory {
+    key: String,
+    epic: String,
+    story: Story,
+}
+
+impl Rule {
+    fn wants(&self, event_type: &str) -> bool {
+        self.event == "*" || self.event == event_type
+    }
+
+    // runs the script for one event, returning the changes it asked for
+    fn run(&self, event: &Value, db_state: &Rc<DBState>) -> Result<Vec<RuleAction>> {
+        let script = self.script.display();
+        let source = fs::read_to_string(&self.script).with_context(|| format!("Couldn't read the rule {script}"))?;
+
+        let time_limit = Duration::from_millis(self.time_limit_ms.unwrap_or(DEFAULT_TIME_LIMIT_MS));
+        let actions = Rc::new(RefCell::new(vec![]));
+        let engine = engine(Rc::clone(db_state), Rc::clone(&actions), time_limit);
+
+        let mut scope = Scope::new();
+        let event = rhai::serde::to_dynamic(event).map_err(|error| anyhow!("{error}"))?;
+        scope.push_constant("event", event);
+
+        if let Err(error) = engine.run_with_scope(&mut scope, &source) {
+            return Err(match *error {
+                EvalAltResult::ErrorTerminated(..) => {
+                    anyhow!("The rule {script} took longer than {}ms and was stopped", time_limit.as_millis())
+                }
+                error => anyhow!("The rule {script} failed: {error}"),
+            });
+        }
+
+        let actions = actions.borrow().clone();
+        Ok(actions)
+    }
+}
+
+// an engine that can't reach anything outside of the database it is given, and stops the script once its time is up
+fn engine(db_state: Rc<DBState>, actions: Rc<RefCell<Vec<RuleAction>>>, time_limit: Duration) -> Engine {
+    let mut engine = Engine::new();
+
+    //rules run in the background of other commands, so there is nowhere for print to go, and scripts can't load other files
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_call_levels(32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+
+    let deadline = Instant::now() + time_limit;
+    engine.on_progress(move |_| (Instant::now() > deadline).then_some(Dynamic::UNIT));
+
+    engine.register_type_with_name::<ScriptEpic>("Epic");
+    engine.register_get("key", |epic: &mut ScriptEpic| epic.key.clone());
+    engine.register_get("name", |epic: &mut ScriptEpic| epic.epic.name.clone());
+    engine.register_get("description", |epic: &mut ScriptEpic| epic.epic.description.clone());
+    engine.register_get("status", |epic: &mut ScriptEpic| format!("{:?}", epic.epic.status));
+    engine.register_get("stories", |epic: &mut ScriptEpic| epic.stories.clone());
+
+    engine.register_type_with_name::<ScriptStory>("Story");
+    engine.register_get("key", |story: &mut ScriptStory| story.key.clone());
+    engine.register_get("epic", |story: &mut ScriptStory| story.epic.clone());
+    engine.register_get("name", |story: &mut ScriptStory| story.story.name.clone());
+    engine.register_get("description", |story: &mut ScriptStory| story.story.description.clone());
+    engine.register_get("status", |story: &mut ScriptStory| format!("{:?}", story.story.status));
+    engine.register_get("estimate", |story: &mut ScriptStory| match story.story.estimate {
+        Some(points) => Dynamic::from(points as i64),
+        None => Dynamic::UNIT,
+    });
+    engine.register_get("assignee", |story: &mut ScriptStory| match &story.story.assignee {
+        Some(assignee) => Dynamic::from(assignee.clone()),
+        None => Dynamic::UNIT,
+    });
+
+    let state = Rc::clone(&db_state);
+    engine.register_fn("epic", move |key: &str| find_epic(&state, key).map(|epic_id| script_epic(&state, epic_id)));
+    let state = Rc::clone(&db_state);
+    engine.register_fn("story", move |key: &str| find_story(&state, key).map(|story_id| script_story(&state, story_id)));
+    let state = Rc::clone(&db_state);
+    engine.register_fn("epics", move || {
+        let mut epic_ids: Vec<DbIndex> = state.epics.keys().copied().collect();
+        epic_ids.sort();
+        epic_ids.into_iter().map(|epic_id| Dynamic::from(script_epic(&state, epic_id))).collect::<Array>()
+    });
+
+    //changes are only collected here, a script keeps seeing the database as it was when the rule started
+    //asking for something that is already so isn't a change, which keeps rules from triggering each other for nothing
+    let (state, queue) = (Rc::clone(&db_state), Rc::clone(&actions));
+    engine.register_fn("set_epic_status", move |key: &str, status: &str| -> Result<(), Box<EvalAltResult>> {
+        let epic_id = find_epic(&state, key)?;
+        let status = script_status(status)?;
+        if state.epics[&epic_id].status != status {
+            queue.borrow_mut().push(RuleAction::SetEpicStatus { epic_id, status });
+        }
+        Ok(())
+    });
+    let (state, queue) = (Rc::clone(&db_state), Rc::clone(&actions));
+    engine.register_fn("set_story_status", move |key: &str, status: &str| -> Result<(), Box<EvalAltResult>> {
+        let story_id = find_story(&state, key)?;
+        let status = script_status(status)?;
+        if state.stories[&story_id].status != status {
+            queue.borrow_mut().push(RuleAction::SetStoryStatus { story_id, status });
+        }
+        Ok(())
+    });
+    let (state, queue) = (Rc::clone(&db_state), Rc::clone(&actions));
+    engine.register_fn("set_assignee", move |key: &str, assignee: &str| -> Result<(), Box<EvalAltResult>> {
+        let story_id = find_story(&state, key)?;
+        let assignee = Some(assignee.to_owned());
+        if state.stories[&story_id].assignee != assignee {
+            queue.borrow_mut().push(RuleAction::SetStoryAssignee { story_id, assignee });
+        }
+        Ok(())
+    });
+    let (state, queue) = (Rc::clone(&db_state), Rc::clone(&actions));
+    engine.register_fn("unassign", move |key: &str| -> Result<(), Box<EvalAltResult>> {
+        let story_id = find_story(&state, key)?;
+        if state.stories[&story_id].assignee.is_some() {
+            queue.borrow_mut().push(RuleAction::SetStoryAssignee { story_id, assignee: None });
+        }
+        Ok(())
+    });
+    let (state, queue) = (Rc::clone(&db_state), Rc::clone(&actions));
+    engine.register_fn("set_estimate", move |key: &str, points: i64| -> Result<(), Box<EvalAltResult>> {
+        let story_id = find_story(&state, key)?;
+        let estimate = Some(StoryPoints::try_from(points).map_err(|_| format!("{points} is not a number of story points"))?);
+        if state.stories[&story_id].estimate != estimate {
+            queue.borrow_mut().push(RuleAction::SetStoryEstimate { story_id, estimate });
+        }
+        Ok(())
+    });
+    let (state, queue) = (Rc::clone(&db_state), Rc::clone(&actions));
+    engine.register_fn("create_story", move |epic_key: &str, name: &str, description: &str| -> Result<(), Box<EvalAltResult>> {
+        let epic_id = find_epic(&state, epic_key)?;
+        queue.borrow_mut().push(RuleAction::CreateStory { epic_id, name: name.to_owned(), description: description.to_owned() });
+        Ok(())
+    });
+
+    engine
+}
+
+fn find_epic(db_state: &DBState, key: &str) -> Result<DbIndex, Box<EvalAltResult>> {
+    db_state
+        .resolve_key(key)
+        .filter(|id| db_state.epics.contains_key(id))
+        .ok_or_else(|| format!("There is no epic {key}").into())
+}
+
+fn find_story(db_state: &DBState, key: &str) -> Result<DbIndex, Box<EvalAltResult>> {
+    db_state
+        .resolve_key(key)
+        .filter(|id| db_state.stories.contains_key(id))
+        .ok_or_else(|| format!("There is no story {key}").into())
+}
+
+fn script_status(text: &str) -> Result<Status, Box<EvalAltResult>> {
+    parse_status(text).ok_or_else(|| format!("{text} is not a status, expected Open, InProgress, Resolved or Closed").into())
+}
+
+fn script_epic(db_state: &DBState, epic_id: DbIndex) -> ScriptEpic {
+    let epic = db_state.epics[&epic_id].clone();
+    let stories = epic
+        .stories
+        .iter()
+        .filter(|story_id| db_state.stories.contains_key(story_id))
+        .map(|story_id| Dynamic::from(script_story(db_state, *story_id)))
+        .collect();
+
+    ScriptEpic { key: db_state.display_key(epic_id), epic, stories }
+}
+
+fn script_story(db_state: &DBState, story_id: DbIndex) -> ScriptStory {
+    let epic_id = db_state.epics.iter().find(|(_, epic)| epic.stories.contains(&story_id)).map(|(epic_id, _)| *epic_id);
+
+    ScriptStory {
+        key: db_state.display_key(story_id),
+        epic: epic_id.map(|epic_id| db_state.display_key(epic_id)).unwrap_or_default(),
+        story: db_state.stories[&story_id].clone(),
+    }
+}
+
+// runs the rules for everything that changed between before and after, and then for whatever the rules changed themselves,
+// returning after with all of their changes made
+pub(crate) fn apply_rules(rules: &[Rule], before: &DBState, after: &DBState) -> Result<DBState> {
+    let mut previous = before.clone();
+    let mut current = after.clone();
+
+    for _ in 0..MAX_ROUNDS {
+        let snapshot = Rc::new(current.clone());
+        let mut actions = vec![];
+        for event in changes(&previous, &current, Utc::now()) {
+            let event_type = event["event"].as_str().unwrap_or_default();
+            for rule in rules.iter().filter(|rule| rule.wants(event_type)) {
+                actions.extend(rule.run(&event, &snapshot)?);
+            }
+        }
+
+        if actions.is_empty() {
+            return Ok(current);
+        }
+
+        let scratch = JiraDatabase::in_memory(current.clone(), DBState::default());
+        for action in actions {
+            let description = format!("{action:?}");
+            action.apply(&scratch).with_context(|| format!("A rule asked for a change that can't be made: {description}"))?;
+        }
+        previous = std::mem::replace(&mut current, scratch.read_db()?);
+    }
+
+    Err(anyhow!("The rules were still changing things after {MAX_ROUNDS} rounds, so nothing was saved"))
+}
+
+// a Database that runs the rules for whatever each write changes, and saves their changes along with it
+pub(crate) struct RuleDatabase {
+    pub inner: Box<dyn Database>,
+    pub rules: Vec<Rule>,
+}
+
+impl Database for RuleDatabase {
+    fn read_db(&self) -> Result<DBState> {
+        self.inner.read_db()
+    }
+
+    fn write_db(&self, db_state: &DBState) -> Result<()> {
+        let db_state = match self.inner.read_db() {
+            Ok(before) => apply_rules(&self.rules, &before, db_state)?,
+            Err(_) => db_state.clone(),
+        };
+        self.inner.write_db(&db_state)
+    }
+
+    fn read_archive(&self) -> Result<DBState> {
+        self.inner.read_archive()
+    }
+
+    fn write_archive(&self, archive: &DBState) -> Result<()> {
+        self.inner.write_archive(archive)
+    }
+
+    fn list_backups(&self) -> Result<Vec<Backup>> {
+        self.inner.list_backups()
+    }
+
+    fn restore_backup(&self, id: &str) -> Result<()> {
+        self.inner.restore_backup(id)
+    }
+
+    fn compact(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.inner.compact(before)
+    }
+
+    fn read_db_at(&self, at: DateTime<Utc>) -> Result<DBState> {
+        self.inner.read_db_at(at)
+    }
+
+    fn pending_deliveries(&self) -> Result<Vec<Delivery>> {
+        self.inner.pending_deliveries()
+    }
+
+    fn retry_deliveries(&self) -> Result<usize> {
+        self.inner.retry_deliveries()
+    }
+
+    fn rules(&self) -> Vec<Rule> {
+        self.rules.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use std::path::Path;
+
+    fn rule(dir: &Path, event: &str, source: &str) -> Rule {
+        let script = dir.join(format!("rule{}.rhai", fs::read_dir(dir).unwrap().count()));
+        fs::write(&script, source).unwrap();
+        Rule {
+            event: event.to_owned(),
+            script,
+            time_limit_ms: None,
+        }
+    }
+
+    fn setup(rules: Vec<Rule>) -> JiraDatabase {
+        JiraDatabase {
+            database: Box::new(MockDB::new()),
+        }
+        .with_rules(rules)
+    }
+
+    #[test]
+    fn epic_should_be_resolved_once_all_of_its_stories_are() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup(vec![rule(
+            dir.path(),
+            "story.status_changed",
+            r#"
+                let epic = epic(story(event.item.key).epic);
+                if epic.stories.all(|story| story.status == "Resolved") {
+                    set_epic_status(epic.key, "Resolved");
+                }
+            "#,
+        )]);
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+        let first = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+        let second = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+
+        db.update_story_status(first, Status::Resolved).unwrap();
+        assert_eq!(db.read_db().unwrap().epics[&epic_id].status, Status::Open);
+
+        db.update_story_status(second, Status::Resolved).unwrap();
+        let db_state = db.read_db().unwrap();
+        assert_eq!(db_state.epics[&epic_id].status, Status::Resolved);
+
+        //the rule's change is undone on its own, before the change that triggered it
+        db.undo().unwrap();
+        assert_eq!(db.read_db().unwrap().epics[&epic_id].status, Status::Open);
+        assert_eq!(db.read_db().unwrap().stories[&second].status, Status::Resolved);
+    }
+
+    #[test]
+    fn rules_should_react_to_changes_made_by_other_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup(vec![
+            rule(dir.path(), "epic.created", r#"create_story(event.item.key, "Write the docs", "");"#),
+            rule(dir.path(), "story.created", r#"set_assignee(event.item.key, "sam");"#),
+        ]);
+
+        db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+        let db_state = db.read_db().unwrap();
+        let story = db_state.stories.values().next().unwrap();
+        assert_eq!(story.name, "Write the docs");
+        assert_eq!(story.assignee.as_deref(), Some("sam"));
+    }
+
+    #[test]
+    fn rule_that_runs_too_long_should_be_stopped_and_nothing_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut endless = rule(dir.path(), "*", "loop { }");
+        endless.time_limit_ms = Some(20);
+        let db = setup(vec![endless]);
+
+        let error = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap_err();
+
+        assert!(format!("{error:#}").contains("took longer than 20ms and was stopped"));
+        assert!(db.read_db().unwrap().epics.is_empty());
+    }
+
+    #[test]
+    fn rules_that_undo_each_other_should_give_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup(vec![rule(
+            dir.path(),
+            "epic.status_changed",
+            r#"set_epic_status(event.item.key, if event["new"] == "Open" { "Closed" } else { "Open" });"#,
+        )]);
+        let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+        let error = db.update_epic_status(epic_id, Status::Closed).unwrap_err();
+
+        assert!(error.to_string().contains("still changing things"));
+        assert_eq!(db.read_db().unwrap().epics[&epic_id].status, Status::Open);
+    }
+
+    #[test]
+    fn scripts_should_not_be_able_to_load_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("other.rhai"), "fn x() { 1 }").unwrap();
+        let db = setup(vec![rule(dir.path(), "*", &format!("import \"{}/other\" as other;", dir.path().display()))]);
+
+        let error = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap_err();
+
+        assert!(format!("{error:#}").contains("failed"));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::models::{DBState, DbIndex, ItemKind};
+
+// how many characters of context to keep either side of the first match in a snippet
+const SNIPPET_CONTEXT: usize = 30;
+
+// matches in a name count for more than matches in a description when ranking
+const NAME_WEIGHT: usize = 3;
+const DESCRIPTION_WEIGHT: usize = 1;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SearchResult {
+    pub kind: ItemKind,
+    pub id: DbIndex,
+    pub score: usize,
+    // the part of the name or description around the first match, with every match wrapped in **
+    pub snippet: String,
+}
+
+// splits a query into lowercase terms, where anything inside double quotes is kept together as one phrase
+pub fn parse_terms(query: &str) -> Vec<String> {
+    let mut terms = vec![];
+
+    for (i, part) in query.split('"').enumerate() {
+        //every odd part was inside a pair of quotes
+        if i % 2 == 1 {
+            let phrase = part.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !phrase.is_empty() {
+                terms.push(phrase.to_lowercase());
+            }
+        } else {
+            terms.extend(part.split_whitespace().map(str::to_lowercase));
+        }
+    }
+
+    terms
+}
+
+// searches epic and story names and descriptions, an item has to match every term to be included
+// results are ordered by score, highest first, and then by id
+pub fn search(db_state: &DBState, query: &str) -> Vec<SearchResult> {
+    let terms = parse_terms(query);
+
+    if terms.is_empty() {
+        return vec![];
+    }
+
+    let epics = db_state
+        .epics
+        .iter()
+        .map(|(id, epic)| (ItemKind::Epic, *id, &epic.name, &epic.description));
+    let stories = db_state
+        .stories
+        .iter()
+        .map(|(id, story)| (ItemKind::Story, *id, &story.name, &story.description));
+
+    let mut results: Vec<SearchResult> = epics
+        .chain(stories)
+        .filter_map(|(kind, id, name, description)| {
+            score_item(name, description, &terms).map(|(score, snippet)| SearchResult {
+                kind,
+                id,
+                score,
+                snippet,
+            })
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(a.id.cmp(&b.id)));
+    results
+}
+
+fn score_item(name: &str, description: &str, terms: &[String]) -> Option<(usize, String)> {
+    let mut score = 0;
+
+    for term in terms {
+        let name_hits = find_matches(name, term).len();
+        let description_hits = find_matches(description, term).len();
+
+        if name_hits + description_hits == 0 {
+            return None;
+        }
+
+        score += name_hits * NAME_WEIGHT + description_hits * DESCRIPTION_WEIGHT;
+    }
+
+    //the description usually gives more context, so the snippet comes from there whenever it has a match
+    let description_matches = all_matches(description, terms);
+    let snippet = if description_matches.is_empty() {
+        make_snippet(name, &all_matches(name, terms))
+    } else {
+        make_snippet(description, &description_matches)
+    };
+
+    Some((score, snippet))
+}
+
+// byte ranges in text where term appears, ignoring case
+fn find_matches(text: &str, term: &str) -> Vec<(usize, usize)> {
+    //each lowercased char is paired with the byte range of the original char it came from,
+    //since lowercasing can change how many bytes or chars there are
+    let lowered: Vec<(char, usize, usize)> = text
+        .char_indices()
+        .flat_map(|(start, c)| {
+            let end = start + c.len_utf8();
+            c.to_lowercase().map(move |lower| (lower, start, end))
+        })
+        .collect();
+    let term: Vec<char> = term.chars().collect();
+
+    if term.is_empty() || term.len() > lowered.len() {
+        return vec![];
+    }
+
+    let mut matches: Vec<(usize, usize)> = vec![];
+    let mut i = 0;
+
+    while i + term.len() <= lowered.len() {
+        let window = &lowered[i..i + term.len()];
+        if window.iter().map(|(c, _, _)| *c).eq(term.iter().copied()) {
+            matches.push((window[0].1, window[term.len() - 1].2));
+            i += term.len();
+        } else {
+            i += 1;
+        }
+    }
+
+    matches
+}
+
+// the matches for every term, sorted and with overlapping ranges merged together
+fn all_matches(text: &str, terms: &[String]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = terms
+        .iter()
+        .flat_map(|term| find_matches(text, term))
+        .collect();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+fn make_snippet(text: &str, matches: &[(usize, usize)]) -> String {
+    let Some(&(first_start, first_end)) = matches.first() else {
+        return text.to_owned();
+    };
+
+    //step back and forward a number of chars from the first match, staying on char boundaries
+    let start = text[..first_start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[first_end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| first_end + i);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str("...");
+    }
+
+    let mut position = start;
+    for &(match_start, match_end) in matches {
+        if match_start < start || match_end > end {
+            continue;
+        }
+        snippet.push_str(&text[position..match_start]);
+        snippet.push_str("**");
+        snippet.push_str(&text[match_start..match_end]);
+        snippet.push_str("**");
+        position = match_end;
+    }
+    snippet.push_str(&text[position..end]);
+
+    if end < text.len() {
+        snippet.push_str("...");
+    }
+
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Epic, Story};
+
+    fn setup() -> DBState {
+        let mut db_state = DBState::default();
+
+        db_state.epics.insert(
+            1,
+            Epic::new("User accounts".to_owned(), "Everything to do with login and signup".to_owned()),
+        );
+        db_state.stories.insert(
+            2,
+            Story::new("Login page".to_owned(), "Build the LOGIN form with a remember me box".to_owned()),
+        );
+        db_state.stories.insert(
+            3,
+            Story::new("Sign up page".to_owned(), "New users can sign up with an email address".to_owned()),
+        );
+
+        db_state
+    }
+
+    #[test]
+    fn parse_terms_should_split_words_and_phrases() {
+        assert_eq!(
+            parse_terms(r#"Login "Remember  me" page"#),
+            vec!["login", "remember me", "page"]
+        );
+        assert_eq!(parse_terms("  "), Vec::<String>::new());
+        assert_eq!(parse_terms(r#""unclosed phrase"#), vec!["unclosed phrase"]);
+    }
+
+    #[test]
+    fn search_should_return_nothing_for_empty_query() {
+        assert!(search(&setup(), "").is_empty());
+    }
+
+    #[test]
+    fn search_should_be_case_insensitive_and_rank_results() {
+        let results = search(&setup(), "login");
+
+        let ids: Vec<DbIndex> = results.iter().map(|result| result.id).collect();
+
+        //the story has login in its name as well as its description so it ranks above the epic
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(results[0].kind, ItemKind::Story);
+        assert_eq!(results[0].score, 4);
+        assert_eq!(results[1].kind, ItemKind::Epic);
+        assert_eq!(results[1].score, 1);
+    }
+
+    #[test]
+    fn search_should_require_every_term() {
+        let results = search(&setup(), "login remember");
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 2);
+    }
+
+    #[test]
+    fn search_should_match_phrases() {
+        assert_eq!(search(&setup(), r#""sign up""#).len(), 1);
+        assert!(search(&setup(), r#""up sign""#).is_empty());
+    }
+
+    #[test]
+    fn search_should_highlight_snippets() {
+        let results = search(&setup(), r#"login "remember me""#);
+
+        assert_eq!(
+            results[0].snippet,
+            "Build the **LOGIN** form with a **remember me** box"
+        );
+    }
+
+    #[test]
+    fn make_snippet_should_trim_long_text() {
+        let text = format!("{}needle{}", "a".repeat(50), "b".repeat(50));
+        let snippet = make_snippet(&text, &find_matches(&text, "needle"));
+
+        assert_eq!(
+            snippet,
+            format!("...{}**needle**{}...", "a".repeat(30), "b".repeat(30))
+        );
+    }
+
+    #[test]
+    fn find_matches_should_handle_multibyte_text() {
+        let text = "Überprüfung der ÜBERGABE";
+
+        assert_eq!(find_matches(text, "über"), vec![(0, 5), (18, 23)]);
+    }
+}
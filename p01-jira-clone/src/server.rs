@@ -0,0 +1,559 @@
+use anyhow::anyhow;
+use serde_json::{json, Map, Value};
+use tiny_http::{Header, Method, Server};
+
+use crate::db::JiraDatabase;
+use crate::models::{DBState, DbIndex, Epic, EpicUpdate, Status, Story, StoryPoints, StoryUpdate};
+
+// a json api over JiraDatabase for other tools to use, items are found by the same keys people type in
+//   GET    /epics                  every epic
+//   POST   /epics                  {"name", "description"?, "project"?}
+//   GET    /epics/<key>            one epic
+//   PATCH  /epics/<key>            {"name"?, "description"?, "status"?}
+//   DELETE /epics/<key>            moves the epic and its stories to the trash
+//   PUT    /epics/<key>/status     {"status"}
+//   GET    /epics/<key>/stories    the stories of an epic
+//   POST   /epics/<key>/stories    {"name", "description"?}
+//   GET    /stories                every story
+//   GET    /stories/<key>          one story
+//   PATCH  /stories/<key>          {"name"?, "description"?, "status"?, "assignee"?, "estimate"?}
+//   DELETE /stories/<key>          moves the story to the trash
+//   PUT    /stories/<key>/status   {"status"}
+// errors are answered with {"error": "..."}
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+#[derive(PartialEq, Debug)]
+pub struct Response {
+    pub status: u16,
+    // None for 204 No Content
+    pub body: Option<Value>,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: 200, body: Some(body) }
+    }
+
+    fn created(body: Value) -> Self {
+        Response { status: 201, body: Some(body) }
+    }
+
+    fn no_content() -> Self {
+        Response { status: 204, body: None }
+    }
+}
+
+// an error along with the status code it is answered with
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError {
+            status: 400,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        ApiError {
+            status: 404,
+            message: message.into(),
+        }
+    }
+}
+
+// anything the server didn't catch itself came from JiraDatabase
+// the server checks that items exist before changing them, so these are either the database refusing a change
+// because of the state things are in, or the database file being unreadable or unwritable
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let storage_error = error
+            .chain()
+            .any(|cause| cause.is::<std::io::Error>() || cause.is::<serde_json::Error>());
+
+        ApiError {
+            status: if storage_error { 500 } else { 409 },
+            message: format!("{error:#}"),
+        }
+    }
+}
+
+type ApiResult = Result<Response, ApiError>;
+
+// answers requests one at a time until the server is shut down
+pub fn serve(server: &Server, db: &JiraDatabase) {
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let response = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => handle(db, request.method(), request.url(), &body),
+            Err(_) => error_response(ApiError::bad_request("The request body isn't valid utf-8")),
+        };
+
+        let http_response = match response.body {
+            Some(body) => {
+                let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+                tiny_http::Response::from_string(body.to_string())
+                    .with_status_code(response.status)
+                    .with_header(content_type)
+                    .boxed()
+            }
+            None => tiny_http::Response::empty(response.status).boxed(),
+        };
+
+        //a client that has already gone away doesn't need an answer
+        let _ = request.respond(http_response);
+    }
+}
+
+// routes one request, kept apart from the http side so the api can be used without a socket
+pub fn handle(db: &JiraDatabase, method: &Method, url: &str, body: &str) -> Response {
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    let result = match (method, segments.as_slice()) {
+        (Method::Get, ["epics"]) => list_epics(db),
+        (Method::Post, ["epics"]) => create_epic(db, body),
+        (Method::Get, ["epics", key]) => get_epic(db, key),
+        (Method::Patch, ["epics", key]) => {
+            parse_body(body, &["name", "description", "status"]).and_then(|fields| update_epic(db, key, fields))
+        }
+        (Method::Delete, ["epics", key]) => delete_epic(db, key),
+        (Method::Put, ["epics", key, "status"]) => parse_status_body(body).and_then(|fields| update_epic(db, key, fields)),
+        (Method::Get, ["epics", key, "stories"]) => list_epic_stories(db, key),
+        (Method::Post, ["epics", key, "stories"]) => create_story(db, key, body),
+        (Method::Get, ["stories"]) => list_stories(db),
+        (Method::Get, ["stories", key]) => get_story(db, key),
+        (Method::Patch, ["stories", key]) => parse_body(body, &["name", "description", "status", "assignee", "estimate"])
+            .and_then(|fields| update_story(db, key, fields)),
+        (Method::Delete, ["stories", key]) => delete_story(db, key),
+        (Method::Put, ["stories", key, "status"]) => {
+            parse_status_body(body).and_then(|fields| update_story(db, key, fields))
+        }
+        (_, ["epics"] | ["stories"] | ["epics" | "stories", _] | ["epics", _, "status" | "stories"] | ["stories", _, "status"]) => {
+            Err(ApiError {
+                status: 405,
+                message: format!("{method} isn't supported on {path}"),
+            })
+        }
+        _ => Err(ApiError::not_found(format!("Nothing is served at {path}"))),
+    };
+
+    result.unwrap_or_else(error_response)
+}
+
+fn error_response(error: ApiError) -> Response {
+    Response {
+        status: error.status,
+        body: Some(json!({ "error": error.message })),
+    }
+}
+
+fn list_epics(db: &JiraDatabase) -> ApiResult {
+    let db_state = db.read_db()?;
+
+    let mut epic_ids: Vec<&DbIndex> = db_state.epics.keys().collect();
+    epic_ids.sort();
+
+    Ok(Response::ok(epic_ids.into_iter().map(|id| epic_json(&db_state, *id)).collect()))
+}
+
+fn get_epic(db: &JiraDatabase, key: &str) -> ApiResult {
+    let db_state = db.read_db()?;
+    let epic_id = find_epic(&db_state, key)?;
+    Ok(Response::ok(epic_json(&db_state, epic_id)))
+}
+
+fn create_epic(db: &JiraDatabase, body: &str) -> ApiResult {
+    let mut fields = parse_body(body, &["name", "description", "project"])?;
+    let name = take_string(&mut fields, "name")?.ok_or_else(|| ApiError::bad_request("name is required"))?;
+    let description = take_string(&mut fields, "description")?.unwrap_or_default();
+    let epic = Epic::new(name, description);
+
+    let epic_id = match take_string(&mut fields, "project")? {
+        Some(project) => {
+            if !db.read_db()?.projects.contains_key(&project) {
+                return Err(ApiError::bad_request(format!("There is no project with the key {project}")));
+            }
+            db.create_epic_in_project(&project, epic)?
+        }
+        None => db.create_epic(epic)?,
+    };
+
+    Ok(Response::created(epic_json(&db.read_db()?, epic_id)))
+}
+
+fn update_epic(db: &JiraDatabase, key: &str, mut fields: Map<String, Value>) -> ApiResult {
+    let epic_id = find_epic(&db.read_db()?, key)?;
+
+    //every field is checked and then they are all saved in one write, so a failure never leaves the epic half updated
+    let update = EpicUpdate {
+        name: take_string(&mut fields, "name")?,
+        description: take_string(&mut fields, "description")?,
+        status: take_status(&mut fields)?,
+    };
+    if update != EpicUpdate::default() {
+        db.update_epic(epic_id, update)?;
+    }
+
+    Ok(Response::ok(epic_json(&db.read_db()?, epic_id)))
+}
+
+fn delete_epic(db: &JiraDatabase, key: &str) -> ApiResult {
+    let epic_id = find_epic(&db.read_db()?, key)?;
+    db.delete_epic(epic_id)?;
+    Ok(Response::no_content())
+}
+
+fn list_epic_stories(db: &JiraDatabase, key: &str) -> ApiResult {
+    let db_state = db.read_db()?;
+    let epic_id = find_epic(&db_state, key)?;
+
+    let stories = db_state.epics[&epic_id].stories.iter().map(|id| story_json(&db_state, *id));
+    Ok(Response::ok(stories.collect()))
+}
+
+fn create_story(db: &JiraDatabase, epic_key: &str, body: &str) -> ApiResult {
+    let epic_id = find_epic(&db.read_db()?, epic_key)?;
+
+    let mut fields = parse_body(body, &["name", "description"])?;
+    let name = take_string(&mut fields, "name")?.ok_or_else(|| ApiError::bad_request("name is required"))?;
+    let description = take_string(&mut fields, "description")?.unwrap_or_default();
+
+    let story_id = db.create_story(Story::new(name, description), epic_id)?;
+    Ok(Response::created(story_json(&db.read_db()?, story_id)))
+}
+
+fn list_stories(db: &JiraDatabase) -> ApiResult {
+    let db_state = db.read_db()?;
+
+    let mut story_ids: Vec<&DbIndex> = db_state.stories.keys().collect();
+    story_ids.sort();
+
+    Ok(Response::ok(story_ids.into_iter().map(|id| story_json(&db_state, *id)).collect()))
+}
+
+fn get_story(db: &JiraDatabase, key: &str) -> ApiResult {
+    let db_state = db.read_db()?;
+    let story_id = find_story(&db_state, key)?;
+    Ok(Response::ok(story_json(&db_state, story_id)))
+}
+
+fn update_story(db: &JiraDatabase, key: &str, mut fields: Map<String, Value>) -> ApiResult {
+    let db_state = db.read_db()?;
+    let story_id = find_story(&db_state, key)?;
+
+    let name = take_string(&mut fields, "name")?;
+    let description = take_string(&mut fields, "description")?;
+    let status = take_status(&mut fields)?;
+
+    //null clears the assignee and the estimate, so a missing field and a null one mean different things
+    let assignee = match fields.remove("assignee") {
+        None => None,
+        Some(Value::Null) => Some(None),
+        Some(Value::String(assignee)) => Some(Some(assignee)),
+        Some(_) => return Err(ApiError::bad_request("assignee has to be a string or null")),
+    };
+    let estimate = match fields.remove("estimate") {
+        None => None,
+        Some(Value::Null) => Some(None),
+        Some(value) => {
+            let points: StoryPoints = serde_json::from_value(value)
+                .map_err(|_| ApiError::bad_request("estimate has to be a whole number or null"))?;
+            if !db_state.estimate_scale.contains(&points) {
+                return Err(ApiError::bad_request(format!(
+                    "{points} is not on the estimate scale {:?}",
+                    db_state.estimate_scale
+                )));
+            }
+            Some(Some(points))
+        }
+    };
+
+    //like update_epic, all the fields are saved in one write
+    let update = StoryUpdate {
+        name,
+        description,
+        status,
+        assignee,
+        estimate,
+    };
+    if update != StoryUpdate::default() {
+        db.update_story(story_id, update)?;
+    }
+
+    Ok(Response::ok(story_json(&db.read_db()?, story_id)))
+}
+
+fn delete_story(db: &JiraDatabase, key: &str) -> ApiResult {
+    let db_state = db.read_db()?;
+    let story_id = find_story(&db_state, key)?;
+    let epic_id = epic_of(&db_state, story_id).ok_or_else(|| anyhow!("Story {story_id} isn't in any epic"))?;
+
+    db.delete_story(epic_id, story_id)?;
+    Ok(Response::no_content())
+}
+
+fn find_epic(db_state: &DBState, key: &str) -> Result<DbIndex, ApiError> {
+    db_state
+        .resolve_key(key)
+        .filter(|id| db_state.epics.contains_key(id))
+        .ok_or_else(|| ApiError::not_found(format!("No epic found with the key {key}")))
+}
+
+fn find_story(db_state: &DBState, key: &str) -> Result<DbIndex, ApiError> {
+    db_state
+        .resolve_key(key)
+        .filter(|id| db_state.stories.contains_key(id))
+        .ok_or_else(|| ApiError::not_found(format!("No story found with the key {key}")))
+}
+
+fn epic_of(db_state: &DBState, story_id: DbIndex) -> Option<DbIndex> {
+    db_state
+        .epics
+        .iter()
+        .find(|(_, epic)| epic.stories.contains(&story_id))
+        .map(|(id, _)| *id)
+}
+
+// the other items an epic or story refers to are given by their keys, the same as in the urls
+fn epic_json(db_state: &DBState, epic_id: DbIndex) -> Value {
+    let epic = &db_state.epics[&epic_id];
+    let stories: Vec<String> = epic.stories.iter().map(|id| db_state.display_key(*id)).collect();
+
+    json!({
+        "id": epic_id,
+        "key": db_state.display_key(epic_id),
+        "name": epic.name,
+        "description": epic.description,
+        "status": epic.status,
+        "stories": stories,
+    })
+}
+
+fn story_json(db_state: &DBState, story_id: DbIndex) -> Value {
+    let story = &db_state.stories[&story_id];
+
+    json!({
+        "id": story_id,
+        "key": db_state.display_key(story_id),
+        "epic": epic_of(db_state, story_id).map(|id| db_state.display_key(id)),
+        "name": story.name,
+        "description": story.description,
+        "status": story.status,
+        "assignee": story.assignee,
+        "estimate": story.estimate,
+        "commits": story.commits,
+    })
+}
+
+// a request body has to be a json object with only the given fields in it, an empty body counts as {}
+fn parse_body(body: &str, allowed: &[&str]) -> Result<Map<String, Value>, ApiError> {
+    if body.trim().is_empty() {
+        return Ok(Map::new());
+    }
+
+    let Ok(Value::Object(fields)) = serde_json::from_str(body) else {
+        return Err(ApiError::bad_request("The request body has to be a json object"));
+    };
+
+    if let Some(unknown) = fields.keys().find(|field| !allowed.contains(&field.as_str())) {
+        return Err(ApiError::bad_request(format!(
+            "Unknown field {unknown}, expected one of {}",
+            allowed.join(", ")
+        )));
+    }
+
+    Ok(fields)
+}
+
+fn take_string(fields: &mut Map<String, Value>, field: &str) -> Result<Option<String>, ApiError> {
+    match fields.remove(field) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(ApiError::bad_request(format!("{field} has to be a string"))),
+    }
+}
+
+fn take_status(fields: &mut Map<String, Value>) -> Result<Option<Status>, ApiError> {
+    match fields.remove("status") {
+        None => Ok(None),
+        Some(value) => serde_json::from_value(value)
+            .map(Some)
+            .map_err(|_| ApiError::bad_request("status has to be one of Open, InProgress, Resolved, Closed")),
+    }
+}
+
+// the status endpoints are a patch with only the status in it
+fn parse_status_body(body: &str) -> Result<Map<String, Value>, ApiError> {
+    let fields = parse_body(body, &["status"])?;
+
+    if !fields.contains_key("status") {
+        return Err(ApiError::bad_request("status is required"));
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    // starts a server on a free port with an empty database, which is made inside the server's thread
+    // since a JiraDatabase isn't Send, returning the port to send requests to
+    fn start_server() -> u16 {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+
+        std::thread::spawn(move || {
+            let db = JiraDatabase {
+                database: Box::new(MockDB::new()),
+            };
+            serve(&server, &db);
+        });
+
+        port
+    }
+
+    // sends a request over a real socket and returns the status code and the parsed body
+    fn request(port: u16, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let body = if body.is_empty() { Value::Null } else { serde_json::from_str(body).unwrap() };
+        (status, body)
+    }
+
+    #[test]
+    fn epics_should_be_created_read_updated_and_deleted() {
+        let port = start_server();
+
+        let (status, epic) = request(port, "POST", "/epics", r#"{"name": "Accounts", "description": "Signing in"}"#);
+        assert_eq!(status, 201);
+        assert_eq!(epic["key"], "1");
+        assert_eq!(epic["status"], "Open");
+
+        let (status, epics) = request(port, "GET", "/epics", "");
+        assert_eq!(status, 200);
+        assert_eq!(epics.as_array().unwrap().len(), 1);
+
+        let (status, epic) = request(port, "PATCH", "/epics/1", r#"{"name": "Users", "status": "InProgress"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(epic["name"], "Users");
+        assert_eq!(epic["description"], "Signing in");
+        assert_eq!(epic["status"], "InProgress");
+
+        let (status, epic) = request(port, "PUT", "/epics/1/status", r#"{"status": "Closed"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(epic["status"], "Closed");
+
+        let (status, body) = request(port, "DELETE", "/epics/1", "");
+        assert_eq!(status, 204);
+        assert_eq!(body, Value::Null);
+
+        let (status, body) = request(port, "GET", "/epics/1", "");
+        assert_eq!(status, 404);
+        assert_eq!(body["error"], "No epic found with the key 1");
+    }
+
+    #[test]
+    fn stories_should_be_created_read_updated_and_deleted() {
+        let port = start_server();
+        request(port, "POST", "/epics", r#"{"name": "Accounts"}"#);
+
+        let (status, story) = request(port, "POST", "/epics/1/stories", r#"{"name": "Login page"}"#);
+        assert_eq!(status, 201);
+        assert_eq!(story["key"], "2");
+        assert_eq!(story["epic"], "1");
+
+        let (status, story) = request(
+            port,
+            "PATCH",
+            "/stories/2",
+            r#"{"description": "With a password", "assignee": "sam", "estimate": 5}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(story["description"], "With a password");
+        assert_eq!(story["assignee"], "sam");
+        assert_eq!(story["estimate"], 5);
+
+        let (_, story) = request(port, "PATCH", "/stories/2", r#"{"assignee": null}"#);
+        assert_eq!(story["assignee"], Value::Null);
+        assert_eq!(story["estimate"], 5);
+
+        let (status, story) = request(port, "PUT", "/stories/2/status", r#"{"status": "Resolved"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(story["status"], "Resolved");
+
+        let (_, stories) = request(port, "GET", "/epics/1/stories", "");
+        assert_eq!(stories[0]["name"], "Login page");
+        let (_, epic) = request(port, "GET", "/epics/1", "");
+        assert_eq!(epic["stories"], json!(["2"]));
+
+        let (status, _) = request(port, "DELETE", "/stories/2", "");
+        assert_eq!(status, 204);
+        let (_, stories) = request(port, "GET", "/stories", "");
+        assert_eq!(stories, json!([]));
+    }
+
+    #[test]
+    fn bad_requests_should_get_matching_status_codes() {
+        let port = start_server();
+        request(port, "POST", "/epics", r#"{"name": "Accounts"}"#);
+        request(port, "POST", "/epics/1/stories", r#"{"name": "Login page"}"#);
+
+        assert_eq!(request(port, "GET", "/stories/9", "").0, 404);
+        //a story's key doesn't find an epic
+        assert_eq!(request(port, "GET", "/epics/2", "").0, 404);
+        assert_eq!(request(port, "GET", "/sprints", "").0, 404);
+        assert_eq!(request(port, "PUT", "/epics", "").0, 405);
+        assert_eq!(request(port, "POST", "/epics", "not json").0, 400);
+        assert_eq!(request(port, "POST", "/epics", r#"{"description": "no name"}"#).0, 400);
+        assert_eq!(request(port, "POST", "/epics", r#"{"name": "A", "owner": "sam"}"#).0, 400);
+        assert_eq!(request(port, "POST", "/epics", r#"{"name": "A", "project": "NOPE"}"#).0, 400);
+        assert_eq!(request(port, "PATCH", "/stories/2", r#"{"status": "Done"}"#).0, 400);
+        assert_eq!(request(port, "PATCH", "/stories/2", r#"{"estimate": 4}"#).0, 400);
+        assert_eq!(request(port, "PUT", "/stories/2/status", r#"{"name": "Renamed"}"#).0, 400);
+
+        //nothing was changed by the requests that failed
+        let (_, story) = request(port, "GET", "/stories/2", "");
+        assert_eq!(story["name"], "Login page");
+        assert_eq!(story["status"], "Open");
+    }
+
+    #[test]
+    fn database_errors_should_be_mapped_to_status_codes() {
+        let refused = ApiError::from(anyhow!("Only closed epics can be archived"));
+        assert_eq!(refused.status, 409);
+
+        let io_error = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "read only");
+        let storage = ApiError::from(anyhow::Error::new(io_error).context("Error writing to the database"));
+        assert_eq!(storage.status, 500);
+        assert_eq!(storage.message, "Error writing to the database: read only");
+    }
+
+    #[test]
+    fn handle_should_ignore_query_strings_and_trailing_slashes() {
+        let db = JiraDatabase {
+            database: Box::new(MockDB::new()),
+        };
+
+        assert_eq!(handle(&db, &Method::Get, "/epics/?expand=stories", ""), Response::ok(json!([])));
+    }
+}
@@ -0,0 +1,4 @@
+mod page_helpers;
+mod pages;
+
+pub use pages::*;
@@ -0,0 +1,39 @@
+// pads text with spaces to fill a column, or cuts it short with "..." when it doesn't fit
+pub fn get_column_string(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+
+    if len <= width {
+        return format!("{text:<width$}");
+    }
+
+    if width <= 3 {
+        return ".".repeat(width);
+    }
+
+    let truncated: String = text.chars().take(width - 3).collect();
+    format!("{truncated}...")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_column_string_should_pad_short_text() {
+        assert_eq!(get_column_string("abc", 6), "abc   ");
+        assert_eq!(get_column_string("", 2), "  ");
+    }
+
+    #[test]
+    fn get_column_string_should_keep_text_that_fits() {
+        assert_eq!(get_column_string("abcdef", 6), "abcdef");
+    }
+
+    #[test]
+    fn get_column_string_should_truncate_long_text() {
+        assert_eq!(get_column_string("abcdefgh", 6), "abc...");
+        assert_eq!(get_column_string("abcdefgh", 3), "...");
+        assert_eq!(get_column_string("abcdefgh", 1), ".");
+        assert_eq!(get_column_string("abcdefgh", 0), "");
+    }
+}
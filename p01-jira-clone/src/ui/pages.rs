@@ -0,0 +1,588 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::config::DEFAULT_BOARD_COLUMN_WIDTH;
+use crate::db::JiraDatabase;
+use crate::models::{Action, DBState, DbIndex, StatusGroups, Story};
+
+use super::page_helpers::get_column_string;
+
+pub trait Page {
+    fn draw_page(&self) -> Result<()>;
+    fn handle_input(&self, input: &str) -> Result<Option<Action>>;
+}
+
+pub struct HomePage {
+    pub db: Rc<JiraDatabase>,
+}
+
+impl Page for HomePage {
+    fn draw_page(&self) -> Result<()> {
+        println!("----------------------------- EPICS -----------------------------");
+        println!("     id     |               name               |      status      ");
+
+        let db_state = self.db.read_db()?;
+
+        let mut epic_ids: Vec<&DbIndex> = db_state.epics.keys().collect();
+        epic_ids.sort();
+
+        for id in epic_ids {
+            let epic = &db_state.epics[id];
+            println!(
+                "{}| {}| {}",
+                get_column_string(&db_state.display_key(*id), 12),
+                get_column_string(&epic.name, 33),
+                get_column_string(&epic.status.to_string(), 17)
+            );
+        }
+
+        let mut pinned: Vec<&String> = db_state
+            .filters
+            .iter()
+            .filter(|(_, filter)| filter.pinned)
+            .map(|(name, _)| name)
+            .collect();
+        pinned.sort();
+
+        let mut sprint_ids: Vec<&DbIndex> = db_state.sprints.keys().collect();
+        sprint_ids.sort();
+
+        if !sprint_ids.is_empty() {
+            println!();
+            println!("---------------------------- SPRINTS ----------------------------");
+            println!("     id     |               name               |      state       ");
+        }
+
+        for id in sprint_ids {
+            let sprint = &db_state.sprints[id];
+            println!(
+                "{}| {}| {}",
+                get_column_string(&db_state.display_key(*id), 12),
+                get_column_string(&sprint.name, 33),
+                get_column_string(&sprint.state.to_string(), 17)
+            );
+        }
+
+        for name in pinned {
+            println!();
+            println!("----------------------------- {name} -----------------------------");
+
+            //a pinned filter that can no longer run shows why instead of stopping the whole page from drawing
+            match self.db.run_filter(name) {
+                Ok(stories) => {
+                    for (id, story) in stories {
+                        println!(
+                            "{}| {}| {}",
+                            get_column_string(&db_state.display_key(id), 12),
+                            get_column_string(&story.name, 33),
+                            get_column_string(&story.status.to_string(), 17)
+                        );
+                    }
+                }
+                Err(error) => println!("{error:#}"),
+            }
+        }
+
+        println!();
+        println!();
+
+        println!("[q] quit | [b] board | [:key:] board for epic or sprint");
+
+        Ok(())
+    }
+
+    fn handle_input(&self, input: &str) -> Result<Option<Action>> {
+        let db_state = self.db.read_db()?;
+
+        match input {
+            "q" => Ok(Some(Action::Exit)),
+            "b" => Ok(Some(Action::NavigateToBoard { epic_id: None })),
+            input => {
+                if let Some(epic_id) = db_state.resolve_key(input) {
+                    if db_state.epics.contains_key(&epic_id) {
+                        return Ok(Some(Action::NavigateToBoard {
+                            epic_id: Some(epic_id),
+                        }));
+                    }
+                    if db_state.sprints.contains_key(&epic_id) {
+                        return Ok(Some(Action::NavigateToSprint { sprint_id: epic_id }));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+pub struct BoardPage {
+    pub epic_id: Option<DbIndex>,
+    pub db: Rc<JiraDatabase>,
+    // the story id of the card that h/l will move, kept in a Cell because pages are only borrowed immutably
+    pub selected: Cell<Option<DbIndex>>,
+    // the width of one column on the board, not counting the separators between columns
+    pub column_width: usize,
+}
+
+impl BoardPage {
+    pub fn new(epic_id: Option<DbIndex>, db: Rc<JiraDatabase>) -> Self {
+        BoardPage {
+            epic_id,
+            db,
+            selected: Cell::new(None),
+            column_width: DEFAULT_BOARD_COLUMN_WIDTH,
+        }
+    }
+
+    // reads the board and makes sure the selection points at a card that is still on it
+    fn load_board(&self) -> Result<StatusGroups> {
+        let board = self.db.get_board(self.epic_id)?;
+        keep_selection_on_board(&board, &self.selected);
+        Ok(board)
+    }
+}
+
+impl Page for BoardPage {
+    fn draw_page(&self) -> Result<()> {
+        let board = self.load_board()?;
+        let db_state = self.db.read_db()?;
+
+        match self.epic_id {
+            Some(epic_id) => println!(
+                "------------------------------------- BOARD FOR EPIC {} -------------------------------------",
+                db_state.display_key(epic_id)
+            ),
+            None => println!("--------------------------------------------- BOARD ---------------------------------------------"),
+        }
+
+        print!("{}", render_board(&board, self.selected.get(), &db_state, self.column_width));
+
+        println!();
+        println!();
+
+        println!("[p] previous | [q] quit | [j] next card | [k] previous card | [h] move left | [l] move right | [:key:] select card");
+
+        Ok(())
+    }
+
+    fn handle_input(&self, input: &str) -> Result<Option<Action>> {
+        let board = self.load_board()?;
+        handle_board_input(&self.db, &board, &self.selected, input)
+    }
+}
+
+// the stories of one sprint laid out like the board, so they can be moved along while the sprint runs
+pub struct SprintPage {
+    pub sprint_id: DbIndex,
+    pub db: Rc<JiraDatabase>,
+    pub selected: Cell<Option<DbIndex>>,
+    pub column_width: usize,
+}
+
+impl SprintPage {
+    pub fn new(sprint_id: DbIndex, db: Rc<JiraDatabase>) -> Self {
+        SprintPage {
+            sprint_id,
+            db,
+            selected: Cell::new(None),
+            column_width: DEFAULT_BOARD_COLUMN_WIDTH,
+        }
+    }
+
+    fn load_board(&self) -> Result<StatusGroups> {
+        let board = self.db.get_sprint_stories_by_status(self.sprint_id)?;
+        keep_selection_on_board(&board, &self.selected);
+        Ok(board)
+    }
+}
+
+impl Page for SprintPage {
+    fn draw_page(&self) -> Result<()> {
+        let board = self.load_board()?;
+        let db_state = self.db.read_db()?;
+        let sprint = &db_state.sprints[&self.sprint_id];
+
+        println!(
+            "---------------------------------- SPRINT {} {} ({}) ----------------------------------",
+            db_state.display_key(self.sprint_id),
+            sprint.name,
+            sprint.state
+        );
+        println!("{} to {}", sprint.start, sprint.end);
+        if !sprint.goal.is_empty() {
+            println!("Goal: {}", sprint.goal);
+        }
+        println!();
+
+        print!("{}", render_board(&board, self.selected.get(), &db_state, self.column_width));
+
+        println!();
+        println!();
+
+        println!("[p] previous | [q] quit | [j] next card | [k] previous card | [h] move left | [l] move right | [:key:] select card");
+
+        Ok(())
+    }
+
+    fn handle_input(&self, input: &str) -> Result<Option<Action>> {
+        let board = self.load_board()?;
+        handle_board_input(&self.db, &board, &self.selected, input)
+    }
+}
+
+// points the selection at the first card when the selected card isn't on the board (any more)
+fn keep_selection_on_board(board: &StatusGroups, selected: &Cell<Option<DbIndex>>) {
+    let selected_is_on_board = selected.get().is_some_and(|id| find_card(board, id).is_some());
+
+    if !selected_is_on_board {
+        let first_card = board.iter().flat_map(|(_, cards)| cards.first()).next();
+        selected.set(first_card.map(|(id, _)| *id));
+    }
+}
+
+// the keys that the board and the sprint page share, for moving the selection and the selected card
+fn handle_board_input(db: &JiraDatabase, board: &StatusGroups, selected_card: &Cell<Option<DbIndex>>, input: &str) -> Result<Option<Action>> {
+    let selected = match selected_card.get() {
+        Some(id) => find_card(board, id).map(|(column, row)| (id, column, row)),
+        None => None,
+    };
+
+    match input {
+        "p" => Ok(Some(Action::NavigatePreviousPage)),
+        "q" => Ok(Some(Action::Exit)),
+        "j" | "k" => {
+            if let Some((_, column, row)) = selected {
+                let cards = &board[column].1;
+                let row = if input == "j" {
+                    (row + 1).min(cards.len() - 1)
+                } else {
+                    row.saturating_sub(1)
+                };
+                selected_card.set(Some(cards[row].0));
+            }
+            Ok(None)
+        }
+        "h" | "l" => {
+            let Some((story_id, column, _)) = selected else {
+                return Ok(None);
+            };
+            let status = &board[column].0;
+            let neighbour = if input == "h" {
+                status.previous()
+            } else {
+                status.next()
+            };
+            Ok(neighbour.map(|status| Action::MoveStory { story_id, status }))
+        }
+        input => {
+            if let Some(story_id) = db.read_db()?.resolve_key(input) {
+                if find_card(board, story_id).is_some() {
+                    selected_card.set(Some(story_id));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+// the (column, row) position of a story on the board
+fn find_card(board: &StatusGroups, story_id: DbIndex) -> Option<(usize, usize)> {
+    board.iter().enumerate().find_map(|(column, (_, cards))| {
+        cards
+            .iter()
+            .position(|(id, _)| *id == story_id)
+            .map(|row| (column, row))
+    })
+}
+
+fn get_card_string(key: &str, story: &Story, selected: bool, width: usize) -> String {
+    let marker = if selected { ">" } else { " " };
+    let card = match &story.assignee {
+        Some(assignee) => format!("{marker} {key} {} @{assignee}", story.name),
+        None => format!("{marker} {key} {}", story.name),
+    };
+    get_column_string(&card, width)
+}
+
+// lays the board out as one column per status with a row for each card, db_state is only used for the cards' keys
+fn render_board(board: &StatusGroups, selected: Option<DbIndex>, db_state: &DBState, column_width: usize) -> String {
+    let header: Vec<String> = board
+        .iter()
+        .map(|(status, _)| get_column_string(&format!("  {status}"), column_width))
+        .collect();
+
+    let mut output = header.join("|").trim_end().to_owned();
+    output.push('\n');
+
+    let rows = board.iter().map(|(_, cards)| cards.len()).max().unwrap_or(0);
+
+    for row in 0..rows {
+        let cells: Vec<String> = board
+            .iter()
+            .map(|(_, cards)| match cards.get(row) {
+                Some((id, story)) => {
+                    get_card_string(&db_state.display_key(*id), story, selected == Some(*id), column_width)
+                }
+                None => get_column_string("", column_width),
+            })
+            .collect();
+        output.push_str(cells.join("|").trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use crate::models::{Epic, Status};
+
+    mod home_page {
+        use super::*;
+
+        #[test]
+        fn draw_page_should_not_throw_error() {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+
+            let page = HomePage { db };
+            assert!(page.draw_page().is_ok());
+        }
+
+        #[test]
+        fn draw_page_should_not_throw_error_for_broken_pinned_filter() {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+
+            let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+            db.save_filter("epic", &format!("epic = {epic_id}")).unwrap();
+            db.pin_filter("epic", true).unwrap();
+            db.delete_epic(epic_id).unwrap();
+
+            let page = HomePage { db };
+            assert!(page.draw_page().is_ok());
+        }
+
+        #[test]
+        fn handle_input_should_not_throw_error() {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+
+            let page = HomePage { db };
+            assert!(page.handle_input("").is_ok());
+        }
+
+        #[test]
+        fn handle_input_should_return_the_correct_actions() {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+
+            let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+
+            let page = HomePage { db };
+
+            assert_eq!(page.handle_input("q").unwrap(), Some(Action::Exit));
+            assert_eq!(
+                page.handle_input("b").unwrap(),
+                Some(Action::NavigateToBoard { epic_id: None })
+            );
+            assert_eq!(
+                page.handle_input(&epic_id.to_string()).unwrap(),
+                Some(Action::NavigateToBoard {
+                    epic_id: Some(epic_id)
+                })
+            );
+            assert_eq!(page.handle_input("999").unwrap(), None);
+            assert_eq!(page.handle_input("j983f2j").unwrap(), None);
+        }
+    }
+
+    mod board_page {
+        use super::*;
+
+        fn setup() -> (Rc<JiraDatabase>, DbIndex, DbIndex, DbIndex) {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+
+            let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+            let story_1 = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+            let story_2 = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+
+            (db, epic_id, story_1, story_2)
+        }
+
+        #[test]
+        fn draw_page_should_not_throw_error() {
+            let (db, epic_id, _, _) = setup();
+
+            let page = BoardPage::new(None, Rc::clone(&db));
+            assert!(page.draw_page().is_ok());
+
+            let page = BoardPage::new(Some(epic_id), db);
+            assert!(page.draw_page().is_ok());
+        }
+
+        #[test]
+        fn draw_page_should_throw_error_for_invalid_epic_id() {
+            let (db, _, _, _) = setup();
+
+            let page = BoardPage::new(Some(999), db);
+            assert!(page.draw_page().is_err());
+        }
+
+        #[test]
+        fn handle_input_should_return_the_correct_actions() {
+            let (db, _, story_1, _) = setup();
+
+            let page = BoardPage::new(None, db);
+
+            assert_eq!(page.handle_input("p").unwrap(), Some(Action::NavigatePreviousPage));
+            assert_eq!(page.handle_input("q").unwrap(), Some(Action::Exit));
+            assert_eq!(page.handle_input("h").unwrap(), None);
+            assert_eq!(
+                page.handle_input("l").unwrap(),
+                Some(Action::MoveStory {
+                    story_id: story_1,
+                    status: Status::InProgress
+                })
+            );
+            assert_eq!(page.handle_input("j983f2j").unwrap(), None);
+        }
+
+        #[test]
+        fn handle_input_should_move_the_selection() {
+            let (db, _, story_1, story_2) = setup();
+
+            let page = BoardPage::new(None, Rc::clone(&db));
+
+            assert_eq!(page.handle_input("j").unwrap(), None);
+            assert_eq!(page.selected.get(), Some(story_2));
+
+            assert_eq!(page.handle_input("j").unwrap(), None);
+            assert_eq!(page.selected.get(), Some(story_2));
+
+            assert_eq!(page.handle_input("k").unwrap(), None);
+            assert_eq!(page.selected.get(), Some(story_1));
+
+            assert_eq!(page.handle_input(&story_2.to_string()).unwrap(), None);
+            assert_eq!(page.selected.get(), Some(story_2));
+
+            db.update_story_status(story_2, Status::Closed).unwrap();
+
+            assert_eq!(page.handle_input("h").unwrap(), Some(Action::MoveStory {
+                story_id: story_2,
+                status: Status::Resolved
+            }));
+            assert_eq!(page.handle_input("l").unwrap(), None);
+        }
+
+        #[test]
+        fn board_should_only_show_stories_of_the_epic() {
+            let (db, epic_id, _, _) = setup();
+
+            let other_epic = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+            let other_story = db.create_story(Story::new("".to_owned(), "".to_owned()), other_epic).unwrap();
+
+            let page = BoardPage::new(Some(epic_id), db);
+
+            assert_eq!(page.handle_input(&other_story.to_string()).unwrap(), None);
+            assert_ne!(page.selected.get(), Some(other_story));
+        }
+    }
+
+    mod sprint_page {
+        use super::*;
+        use crate::models::Sprint;
+        use chrono::NaiveDate;
+
+        #[test]
+        fn sprint_page_should_show_only_the_sprint_stories() {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+            let epic_id = db.create_epic(Epic::new("".to_owned(), "".to_owned())).unwrap();
+            let story_1 = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+            let story_2 = db.create_story(Story::new("".to_owned(), "".to_owned()), epic_id).unwrap();
+            let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+            let sprint_id = db.create_sprint(Sprint::new("".to_owned(), "".to_owned(), day(1), day(14))).unwrap();
+            db.add_story_to_sprint(sprint_id, story_2).unwrap();
+
+            let page = SprintPage::new(sprint_id, db);
+            assert!(page.draw_page().is_ok());
+
+            assert_eq!(page.handle_input(&story_1.to_string()).unwrap(), None);
+            assert_eq!(page.selected.get(), Some(story_2));
+            assert_eq!(
+                page.handle_input("l").unwrap(),
+                Some(Action::MoveStory {
+                    story_id: story_2,
+                    status: Status::InProgress
+                })
+            );
+            assert_eq!(page.handle_input("p").unwrap(), Some(Action::NavigatePreviousPage));
+        }
+
+        #[test]
+        fn home_page_should_open_a_sprint_by_its_key() {
+            let db = Rc::new(JiraDatabase {
+                database: Box::new(MockDB::new()),
+            });
+            let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+            let sprint_id = db.create_sprint(Sprint::new("".to_owned(), "".to_owned(), day(1), day(14))).unwrap();
+
+            let page = HomePage { db };
+            assert!(page.draw_page().is_ok());
+            assert_eq!(page.handle_input(&sprint_id.to_string()).unwrap(), Some(Action::NavigateToSprint { sprint_id }));
+        }
+    }
+
+    #[test]
+    fn render_board_should_put_cards_in_status_columns() {
+        let mut story_1 = Story::new("Login page".to_owned(), "".to_owned());
+        story_1.assignee = Some("sam".to_owned());
+        let story_2 = Story::new("Signup".to_owned(), "".to_owned());
+        let story_3 = Story::new("A story with a name that is much too long".to_owned(), "".to_owned());
+
+        let board: StatusGroups = vec![
+            (Status::Open, vec![(2, story_1), (4, story_3)]),
+            (Status::InProgress, vec![(3, story_2)]),
+            (Status::Resolved, vec![]),
+            (Status::Closed, vec![]),
+        ];
+
+        let rendered = render_board(&board, Some(3), &DBState::default(), DEFAULT_BOARD_COLUMN_WIDTH);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "  OPEN                  |  IN PROGRESS           |  RESOLVED              |  CLOSED"
+        );
+        assert_eq!(
+            lines[1],
+            "  2 Login page @sam     |> 3 Signup              |                        |"
+        );
+        assert_eq!(
+            lines[2],
+            "  4 A story with a na...|                        |                        |"
+        );
+
+        let db_state = DBState {
+            key_prefix: Some("WEB".to_owned()),
+            ..Default::default()
+        };
+        let rendered = render_board(&board, None, &db_state, DEFAULT_BOARD_COLUMN_WIDTH);
+        assert!(rendered.lines().nth(1).unwrap().starts_with("  WEB-2 Login page @sam"));
+
+        let rendered = render_board(&board, None, &DBState::default(), 10);
+        assert_eq!(rendered.lines().nth(1).unwrap(), "  2 Log...|  3 Signup|          |");
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::{to_stable_json, Database};
+use crate::events::changes;
+use crate::models::{Backup, DBState};
+use crate::rules::Rule;
+
+// how long a receiver gets to answer before the delivery counts as failed
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+// the wait after the first failed delivery, doubling after every failure after that up to MAX_BACKOFF
+const FIRST_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+
+// somewhere to POST events to, set up in the config file
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    // the event types to send, like story.status_changed, or every event when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl Webhook {
+    fn wants(&self, event_type: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|wanted| wanted == event_type)
+    }
+}
+
+// an event waiting to be sent to one webhook
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Delivery {
+    pub url: String,
+    pub event: Value,
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_attempt: DateTime<Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+// a Database that notices what each write changed and sends it to the webhooks
+// events are put in a queue file first and only taken out once the receiver has accepted them,
+// so they survive both a receiver that is down and the program exiting before they are sent
+pub(crate) struct WebhookDatabase {
+    pub inner: Box<dyn Database>,
+    pub webhooks: Vec<Webhook>,
+    pub queue_path: PathBuf,
+}
+
+impl WebhookDatabase {
+    fn read_queue(&self) -> Result<Vec<Delivery>> {
+        match fs::read_to_string(&self.queue_path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("{} is not a valid webhook queue", self.queue_path.display())),
+            Err(_) => Ok(vec![]),
+        }
+    }
+
+    fn write_queue(&self, queue: &[Delivery]) -> Result<()> {
+        if queue.is_empty() && !self.queue_path.exists() {
+            return Ok(());
+        }
+        fs::write(&self.queue_path, to_stable_json(&queue)?)?;
+        Ok(())
+    }
+
+    // queues the events for every webhook that wants them and then sends whatever is due
+    fn notify(&self, events: Vec<Value>, now: DateTime<Utc>) -> Result<()> {
+        let mut queue = self.read_queue()?;
+
+        for event in events {
+            let event_type = event["event"].as_str().unwrap_or_default();
+            for webhook in self.webhooks.iter().filter(|webhook| webhook.wants(event_type)) {
+                queue.push(Delivery {
+                    url: webhook.url.clone(),
+                    event: event.clone(),
+                    attempts: 0,
+                    next_attempt: now,
+                    last_error: None,
+                });
+            }
+        }
+
+        if !queue.is_empty() {
+            deliver_due(&mut queue, now, post_event);
+        }
+        self.write_queue(&queue)
+    }
+}
+
+impl Database for WebhookDatabase {
+    fn read_db(&self) -> Result<DBState> {
+        self.inner.read_db()
+    }
+
+    fn write_db(&self, db_state: &DBState) -> Result<()> {
+        //a database that can't be read yet has nothing to compare against, so its first write sends nothing
+        let before = self.inner.read_db().ok();
+        self.inner.write_db(db_state)?;
+
+        let now = Utc::now();
+        let events = match &before {
+            Some(before) => changes(before, db_state, now),
+            None => vec![],
+        };
+
+        //the change itself has been saved at this point, only its events are in question
+        self.notify(events, now)
+            .context("The change was saved, but its webhook events couldn't be queued")
+    }
+
+    fn read_archive(&self) -> Result<DBState> {
+        self.inner.read_archive()
+    }
+
+    fn write_archive(&self, archive: &DBState) -> Result<()> {
+        self.inner.write_archive(archive)
+    }
+
+    fn list_backups(&self) -> Result<Vec<Backup>> {
+        self.inner.list_backups()
+    }
+
+    fn restore_backup(&self, id: &str) -> Result<()> {
+        self.inner.restore_backup(id)
+    }
+
+    fn compact(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.inner.compact(before)
+    }
+
+    fn read_db_at(&self, at: DateTime<Utc>) -> Result<DBState> {
+        self.inner.read_db_at(at)
+    }
+
+    fn pending_deliveries(&self) -> Result<Vec<Delivery>> {
+        self.read_queue()
+    }
+
+    fn retry_deliveries(&self) -> Result<usize> {
+        let mut queue = self.read_queue()?;
+
+        //a manual retry doesn't wait for the backoff
+        let now = Utc::now();
+        queue.iter_mut().for_each(|delivery| delivery.next_attempt = delivery.next_attempt.min(now));
+
+        let delivered = deliver_due(&mut queue, now, post_event);
+        self.write_queue(&queue)?;
+        Ok(delivered)
+    }
+
+    fn rules(&self) -> Vec<Rule> {
+        self.inner.rules()
+    }
+}
+
+fn post_event(url: &str, event: &Value) -> Result<()> {
+    ureq::post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&event.to_string())
+        .map_err(|error| anyhow!("{error}"))?;
+    Ok(())
+}
+
+// how long to wait before trying again after the given number of failed attempts
+fn backoff(attempts: u32) -> TimeDelta {
+    let secs = FIRST_BACKOFF_SECS.saturating_mul(1 << attempts.saturating_sub(1).min(20));
+    TimeDelta::seconds(secs.min(MAX_BACKOFF_SECS))
+}
+
+// sends every delivery that is due, removing the ones that were accepted and returning how many there were
+// once a delivery to a url fails the later ones to that url wait too, so a receiver always gets events in order
+fn deliver_due(queue: &mut Vec<Delivery>, now: DateTime<Utc>, send: impl Fn(&str, &Value) -> Result<()>) -> usize {
+    let mut blocked_urls: Vec<String> = vec![];
+    let mut delivered = 0;
+
+    queue.retain_mut(|delivery| {
+        if blocked_urls.contains(&delivery.url) || delivery.next_attempt > now {
+            blocked_urls.push(delivery.url.clone());
+            return true;
+        }
+
+        match send(&delivery.url, &delivery.event) {
+            Ok(()) => {
+                delivered += 1;
+                false
+            }
+            Err(error) => {
+                delivery.attempts += 1;
+                delivery.next_attempt = now + backoff(delivery.attempts);
+                delivery.last_error = Some(error.to_string());
+                blocked_urls.push(delivery.url.clone());
+                true
+            }
+        }
+    });
+
+    delivered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::MockDB;
+    use crate::db::JiraDatabase;
+    use crate::models::{Epic, EpicUpdate, Status, Story};
+    use serde_json::json;
+    use std::sync::mpsc::{self, Receiver};
+
+    // a stand-in receiver on a free port that answers the given status codes in turn, and then 200 for the rest
+    // returns its url and the bodies it was sent
+    fn start_receiver(statuses: Vec<u16>) -> (String, Receiver<Value>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/hook", server.server_addr().to_ip().unwrap().port());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let mut statuses = statuses.into_iter();
+            for mut request in server.incoming_requests() {
+                let status = statuses.next().unwrap_or(200);
+                if status == 200 {
+                    let mut body = String::new();
+                    request.as_reader().read_to_string(&mut body).unwrap();
+                    sender.send(serde_json::from_str(&body).unwrap()).unwrap();
+                }
+                request.respond(tiny_http::Response::empty(status)).unwrap();
+            }
+        });
+
+        (url, receiver)
+    }
+
+    // a url that nothing is listening on
+    fn closed_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://127.0.0.1:{}/hook", listener.local_addr().unwrap().port())
+    }
+
+    fn setup(url: &str, events: &[&str], queue_path: PathBuf) -> JiraDatabase {
+        JiraDatabase {
+            database: Box::new(MockDB::new()),
+        }
+        .with_webhooks(
+            vec![Webhook {
+                url: url.to_owned(),
+                events: events.iter().map(|event| event.to_string()).collect(),
+            }],
+            queue_path,
+        )
+    }
+
+    #[test]
+    fn status_changes_should_be_posted_to_the_webhook() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, received) = start_receiver(vec![]);
+        let db = setup(&url, &["story.status_changed"], dir.path().join("webhooks.json"));
+
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        let story_id = db.create_story(Story::new("Login".to_owned(), "".to_owned()), epic_id).unwrap();
+        db.update_story_status(story_id, Status::InProgress).unwrap();
+
+        let event = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event["event"], "story.status_changed");
+        assert_eq!(event["item"], json!({ "kind": "story", "id": story_id, "key": "2" }));
+        assert_eq!(event["old"], "Open");
+        assert_eq!(event["new"], "InProgress");
+
+        //the creations weren't asked for
+        assert!(received.try_recv().is_err());
+        assert!(db.database.pending_deliveries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn events_should_be_queued_while_the_receiver_is_down() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join("webhooks.json");
+        let db = setup(&closed_url(), &[], queue_path.clone());
+
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        db.update_epic_status(epic_id, Status::Closed).unwrap();
+
+        let pending = db.database.pending_deliveries().unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].event["event"], "epic.created");
+        assert_eq!(pending[0].attempts, 1);
+        assert!(pending[0].last_error.is_some());
+        //the second one waited behind the first instead of being tried out of order
+        assert_eq!(pending[1].event["event"], "epic.status_changed");
+        assert_eq!(pending[1].attempts, 0);
+
+        //the queue is in a file, so it outlasts the program
+        let saved: Vec<Delivery> = serde_json::from_str(&fs::read_to_string(&queue_path).unwrap()).unwrap();
+        assert_eq!(saved, pending);
+    }
+
+    #[test]
+    fn retry_deliveries_should_send_the_queue_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue_path = dir.path().join("webhooks.json");
+        let (url, received) = start_receiver(vec![503]);
+        let db = setup(&url, &[], queue_path.clone());
+
+        let epic_id = db.create_epic(Epic::new("Accounts".to_owned(), "".to_owned())).unwrap();
+        let update = EpicUpdate {
+            name: Some("Users".to_owned()),
+            ..Default::default()
+        };
+        db.update_epic(epic_id, update).unwrap();
+        assert_eq!(db.database.pending_deliveries().unwrap().len(), 2);
+
+        assert_eq!(db.database.retry_deliveries().unwrap(), 2);
+
+        let first = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        let second = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(first["event"], "epic.created");
+        assert_eq!(first["new"]["name"], "Accounts");
+        assert_eq!(second["event"], "epic.name_changed");
+        assert_eq!(second["new"], "Users");
+        assert!(db.database.pending_deliveries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn deliver_due_should_wait_for_the_backoff() {
+        let now = Utc::now();
+        let mut queue = vec![Delivery {
+            url: "http://example.invalid".to_owned(),
+            event: json!({}),
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
+        }];
+
+        assert_eq!(deliver_due(&mut queue, now, |_, _| Err(anyhow!("refused"))), 0);
+        assert_eq!(queue[0].next_attempt, now + TimeDelta::seconds(30));
+
+        //not due yet, so it isn't sent
+        assert_eq!(deliver_due(&mut queue, now + TimeDelta::seconds(10), |_, _| Ok(())), 0);
+        assert_eq!(deliver_due(&mut queue, now + TimeDelta::seconds(30), |_, _| Ok(())), 1);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn backoff_should_double_up_to_a_limit() {
+        assert_eq!(backoff(1), TimeDelta::seconds(30));
+        assert_eq!(backoff(2), TimeDelta::seconds(60));
+        assert_eq!(backoff(3), TimeDelta::seconds(120));
+        assert_eq!(backoff(50), TimeDelta::seconds(MAX_BACKOFF_SECS));
+    }
+}